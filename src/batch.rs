@@ -0,0 +1,279 @@
+use crate::config::BatchConfig;
+use crate::crawler::{Logger, Store};
+use crate::result::{Brand, Log, LogLevel, Model, Result, Spec};
+use async_trait::async_trait;
+use std::error::Error;
+use std::mem;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tracing::error;
+
+type LogEntry = Log<LogLevel, Box<dyn Error + Send + Sync>>;
+
+// Buffers specs and writes them with `insert_specs` once `max_items` are queued, on every
+// `flush_interval_ms` and on flush. `insert_spec` returns once its spec is written, with the result
+// of the backend for that spec, so a spec is only counted and logged as stored after it was.
+pub struct BatchStore {
+    inner: Arc<dyn Store>,
+    max_items: usize,
+    buffer: Mutex<Vec<(Spec, oneshot::Sender<Result<()>>)>>,
+}
+
+impl BatchStore {
+    pub fn new(inner: Arc<dyn Store>, config: &BatchConfig) -> Arc<Self> {
+        let store = Arc::new(Self {
+            inner,
+            max_items: config.max_items,
+            buffer: Mutex::new(Vec::new()),
+        });
+        spawn_flusher(Arc::downgrade(&store), config.flush_interval_ms, |s| async move { s.write_buffer().await });
+        store
+    }
+
+    async fn write_buffer(&self) {
+        let pending = mem::take(&mut *self.buffer.lock().unwrap());
+        self.write(pending).await;
+    }
+
+    async fn write(&self, pending: Vec<(Spec, oneshot::Sender<Result<()>>)>) {
+        if pending.is_empty() {
+            return;
+        }
+        let (specs, senders): (Vec<Spec>, Vec<_>) = pending.into_iter().unzip();
+        let results = self.inner.insert_specs(&specs).await;
+        for (sender, res) in senders.into_iter().zip(results) {
+            // The caller is gone if its task was cancelled, the spec is written all the same.
+            let _ = sender.send(res);
+        }
+    }
+}
+
+#[async_trait]
+impl Store for BatchStore {
+    async fn insert_spec(&self, spec: &Spec) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        let full = {
+            let mut buffer = self.buffer.lock().unwrap();
+            buffer.push((spec.clone(), sender));
+            if buffer.len() >= self.max_items {
+                mem::take(&mut *buffer)
+            } else {
+                Vec::new()
+            }
+        };
+        self.write(full).await;
+        receiver.await.map_err(|_| "batch store dropped before the spec was written")?
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.write_buffer().await;
+        self.inner.flush().await
+    }
+}
+
+// Buffers log entries like `BatchStore`. Entries the backend rejects are reported on stderr as
+// there is nowhere else to record them. Buffered entries are written before the log is queried.
+pub struct BatchLogger {
+    inner: Arc<dyn Logger>,
+    max_items: usize,
+    buffer: Mutex<Vec<LogEntry>>,
+}
+
+impl BatchLogger {
+    pub fn new(inner: Arc<dyn Logger>, config: &BatchConfig) -> Arc<Self> {
+        let logger = Arc::new(Self {
+            inner,
+            max_items: config.max_items,
+            buffer: Mutex::new(Vec::new()),
+        });
+        spawn_flusher(Arc::downgrade(&logger), config.flush_interval_ms, |l| async move { l.write_buffer().await });
+        logger
+    }
+
+    async fn write_buffer(&self) {
+        let logs = mem::take(&mut *self.buffer.lock().unwrap());
+        self.write(logs).await;
+    }
+
+    async fn write(&self, logs: Vec<LogEntry>) {
+        if logs.is_empty() {
+            return;
+        }
+        let descriptions: Vec<String> = logs.iter().map(describe).collect();
+        let results = self.inner.insert_logs(logs).await;
+        for (description, res) in descriptions.into_iter().zip(results) {
+            if let Err(e) = res {
+                error!(error = %e, "failed to write log entry {}", description);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Logger for BatchLogger {
+    async fn insert_log(&self, log: LogEntry) -> Result<()> {
+        let full = {
+            let mut buffer = self.buffer.lock().unwrap();
+            buffer.push(log);
+            if buffer.len() >= self.max_items {
+                mem::take(&mut *buffer)
+            } else {
+                Vec::new()
+            }
+        };
+        self.write(full).await;
+        Ok(())
+    }
+
+    async fn get_brand_errors(&self) -> Result<Vec<(Brand, String)>> {
+        self.write_buffer().await;
+        self.inner.get_brand_errors().await
+    }
+
+    async fn get_model_errors(&self) -> Result<Vec<(Model, String)>> {
+        self.write_buffer().await;
+        self.inner.get_model_errors().await
+    }
+
+    async fn get_spec_errors(&self) -> Result<Vec<(Spec, String)>> {
+        self.write_buffer().await;
+        self.inner.get_spec_errors().await
+    }
+
+    async fn update_state(&self, id: &str, state: &str) -> Result<()> {
+        self.inner.update_state(id, state).await
+    }
+
+    async fn increment_retry_count(&self, id: &str) -> Result<()> {
+        self.inner.increment_retry_count(id).await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.write_buffer().await;
+        self.inner.flush().await
+    }
+}
+
+// Periodically flushes the batcher until it is dropped.
+fn spawn_flusher<T, F, Fut>(batcher: Weak<T>, interval_ms: u64, flush: F)
+where
+    T: Send + Sync + 'static,
+    F: Fn(Arc<T>) -> Fut + Send + 'static,
+    Fut: std::future::Future<Output = ()> + Send,
+{
+    if interval_ms == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_millis(interval_ms));
+        loop {
+            interval.tick().await;
+            match batcher.upgrade() {
+                Some(batcher) => flush(batcher).await,
+                None => return,
+            }
+        }
+    });
+}
+
+fn describe(log: &LogEntry) -> String {
+    let level = match log {
+        Log::Log(level) | Log::Err(level, _) | Log::Skip(level, _) | Log::Interrupt(level) | Log::Reject(level, _) | Log::Diagnose(level, _) => level,
+    };
+    match level {
+        LogLevel::Brand(brand) => format!("for brand page {}", brand.get_url()),
+        LogLevel::Model(model) => format!("for model page {}", model.get_url()),
+        LogLevel::Spec(spec) => format!("for spec {} {} {}", spec.get_brand(), spec.get_model(), spec.get_year()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BatchLogger, BatchStore};
+    use crate::config::BatchConfig;
+    use crate::crawler::{Logger, Store};
+    use crate::result::{Brand, Log, LogLevel, Model, Result, Spec};
+    use async_trait::async_trait;
+    use std::error::Error;
+    use std::sync::{Arc, Mutex};
+
+    // Rejects specs of the "bad" brand and records the batch sizes it receives.
+    #[derive(Default)]
+    struct PickyStore {
+        batches: Mutex<Vec<usize>>,
+    }
+
+    #[async_trait]
+    impl Store for PickyStore {
+        async fn insert_spec(&self, _: &Spec) -> Result<()> {
+            Err("specs must be written in batches".into())
+        }
+
+        async fn insert_specs(&self, specs: &[Spec]) -> Vec<Result<()>> {
+            self.batches.lock().unwrap().push(specs.len());
+            specs.iter().map(|s| if s.get_brand() == "bad" { Err("duplicate key".into()) } else { Ok(()) }).collect()
+        }
+    }
+
+    #[derive(Default)]
+    struct FailedSpecs(Mutex<Vec<String>>);
+
+    #[async_trait]
+    impl Logger for FailedSpecs {
+        async fn insert_log(&self, log: Log<LogLevel, Box<dyn Error + Send + Sync>>) -> Result<()> {
+            if let Log::Err(LogLevel::Spec(spec), e) = log {
+                self.0.lock().unwrap().push(format!("{}: {}", spec.get_model(), e));
+            }
+            Ok(())
+        }
+        async fn get_brand_errors(&self) -> Result<Vec<(Brand, String)>> {
+            Ok(Vec::new())
+        }
+        async fn get_model_errors(&self) -> Result<Vec<(Model, String)>> {
+            Ok(Vec::new())
+        }
+        async fn get_spec_errors(&self) -> Result<Vec<(Spec, String)>> {
+            Ok(Vec::new())
+        }
+        async fn update_state(&self, _: &str, _: &str) -> Result<()> {
+            Ok(())
+        }
+        async fn increment_retry_count(&self, _: &str) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_batch_store() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let inner = Arc::new(PickyStore::default());
+        let results = rt.block_on(async {
+            let config = BatchConfig { max_items: 2, flush_interval_ms: 50 };
+            let store = BatchStore::new(inner.clone(), &config);
+            let specs: Vec<Spec> = [("good", "a"), ("bad", "b"), ("good", "c")]
+                .iter()
+                .map(|(b, m)| Spec::new(b.to_string(), m.to_string(), "2020".to_owned()))
+                .collect();
+            // Each insert returns once its batch is written, the last one by the timer.
+            let results = futures::future::join_all(specs.iter().map(|s| store.insert_spec(s))).await;
+            results.into_iter().map(|r| r.map_err(|e| e.to_string())).collect::<Vec<_>>()
+        });
+        assert_eq!(*inner.batches.lock().unwrap(), vec![2, 1]);
+        assert_eq!(results, vec![Ok(()), Err("duplicate key".to_owned()), Ok(())]);
+    }
+
+    #[test]
+    fn test_batch_logger_writes_before_queries() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let inner = Arc::new(FailedSpecs::default());
+        rt.block_on(async {
+            let logger = BatchLogger::new(inner.clone(), &BatchConfig::default());
+            let spec = Spec::new("bad".to_owned(), "b".to_owned(), "2020".to_owned());
+            logger.insert_log(Log::Err(LogLevel::Spec(Box::new(spec)), "timeout".into())).await.unwrap();
+            assert!(inner.0.lock().unwrap().is_empty());
+            logger.get_brand_errors().await.unwrap();
+        });
+        assert_eq!(*inner.0.lock().unwrap(), vec!["b: timeout"]);
+    }
+}
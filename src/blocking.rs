@@ -0,0 +1,93 @@
+use crate::crawler::{Logger, Store};
+use crate::result::{Brand, Log, LogLevel, Model, Result, Spec};
+use async_trait::async_trait;
+use std::error::Error;
+use std::sync::Arc;
+use tokio::task::spawn_blocking;
+
+// Synchronous counterpart of `Store` for simple backends, see `Blocking`.
+pub trait BlockingStore: Send + Sync {
+    fn insert_spec(&self, spec: &Spec) -> Result<()>;
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+// Synchronous counterpart of `Logger` for simple backends, see `Blocking`.
+pub trait BlockingLogger: Send + Sync {
+    fn insert_log(&self, log: Log<LogLevel, Box<dyn Error + Send + Sync>>) -> Result<()>;
+    fn get_brand_errors(&self) -> Result<Vec<(Brand, String)>>;
+    fn get_model_errors(&self) -> Result<Vec<(Model, String)>>;
+    fn get_spec_errors(&self) -> Result<Vec<(Spec, String)>>;
+    fn update_state(&self, id: &str, state: &str) -> Result<()>;
+    fn increment_retry_count(&self, id: &str) -> Result<()>;
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+// Runs a blocking store or logger on tokio's blocking thread pool so it never stalls the workers.
+pub struct Blocking<T>(Arc<T>);
+
+impl<T> Blocking<T> {
+    pub fn new(inner: T) -> Self {
+        Self(Arc::new(inner))
+    }
+
+    async fn run<R, F>(&self, f: F) -> Result<R>
+    where
+        T: Send + Sync + 'static,
+        R: Send + 'static,
+        F: FnOnce(&T) -> Result<R> + Send + 'static,
+    {
+        let inner = self.0.clone();
+        spawn_blocking(move || f(&inner)).await?
+    }
+}
+
+#[async_trait]
+impl<T: BlockingStore + 'static> Store for Blocking<T> {
+    async fn insert_spec(&self, spec: &Spec) -> Result<()> {
+        let spec = spec.clone();
+        self.run(move |s| s.insert_spec(&spec)).await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.run(|s| s.flush()).await
+    }
+}
+
+#[async_trait]
+impl<T: BlockingLogger + 'static> Logger for Blocking<T> {
+    async fn insert_log(&self, log: Log<LogLevel, Box<dyn Error + Send + Sync>>) -> Result<()> {
+        self.run(move |l| l.insert_log(log)).await
+    }
+
+    async fn get_brand_errors(&self) -> Result<Vec<(Brand, String)>> {
+        self.run(|l| l.get_brand_errors()).await
+    }
+
+    async fn get_model_errors(&self) -> Result<Vec<(Model, String)>> {
+        self.run(|l| l.get_model_errors()).await
+    }
+
+    async fn get_spec_errors(&self) -> Result<Vec<(Spec, String)>> {
+        self.run(|l| l.get_spec_errors()).await
+    }
+
+    async fn update_state(&self, id: &str, state: &str) -> Result<()> {
+        let (id, state) = (id.to_owned(), state.to_owned());
+        self.run(move |l| l.update_state(&id, &state)).await
+    }
+
+    async fn increment_retry_count(&self, id: &str) -> Result<()> {
+        let id = id.to_owned();
+        self.run(move |l| l.increment_retry_count(&id)).await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.run(|l| l.flush()).await
+    }
+}
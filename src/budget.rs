@@ -0,0 +1,145 @@
+use crate::config::BudgetConfig;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exceeded {
+    Pages,
+    BrandPages,
+    Depth,
+    Time,
+    Bytes,
+}
+
+impl fmt::Display for Exceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Exceeded::Pages => "page budget exhausted",
+            Exceeded::BrandPages => "brand page budget exhausted",
+            Exceeded::Depth => "pagination depth limit reached",
+            Exceeded::Time => "time budget exhausted",
+            Exceeded::Bytes => "download budget exhausted",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+// Caps on how much a single run may crawl. Pages, time and bytes are global: once one of them is
+// exhausted every further fetch is refused, brand page and depth limits only cut the affected branch.
+#[derive(Debug)]
+pub struct Budget {
+    config: BudgetConfig,
+    started: Instant,
+    pages: AtomicU64,
+    bytes: AtomicU64,
+    brand_pages: Mutex<HashMap<String, u64>>,
+    exhausted: AtomicBool,
+}
+
+impl Budget {
+    pub fn new(config: &BudgetConfig) -> Self {
+        Self {
+            config: config.clone(),
+            started: Instant::now(),
+            pages: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            brand_pages: Mutex::new(HashMap::new()),
+            exhausted: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted.load(Ordering::SeqCst)
+    }
+
+    fn global(&self) -> Option<Exceeded> {
+        let exceeded = if self.config.max_duration_secs.is_some_and(|secs| self.started.elapsed() >= Duration::from_secs(secs)) {
+            Some(Exceeded::Time)
+        } else if self.config.max_bytes.is_some_and(|max| self.bytes.load(Ordering::SeqCst) >= max) {
+            Some(Exceeded::Bytes)
+        } else if self.config.max_pages.is_some_and(|max| self.pages.load(Ordering::SeqCst) >= max) {
+            Some(Exceeded::Pages)
+        } else {
+            None
+        };
+        if exceeded.is_some() {
+            self.exhausted.store(true, Ordering::SeqCst);
+        }
+        exceeded
+    }
+
+    // Reserves one page fetch for the brand, failing without reserving anything when a limit is hit.
+    pub fn acquire_page(&self, brand: &str) -> Result<(), Exceeded> {
+        if let Some(exceeded) = self.global() {
+            return Err(exceeded);
+        }
+        // The brand count is only taken once the global page is, under the same lock so a concurrent
+        // fetch of the brand cannot pass the check in between.
+        let mut brand_pages = self.brand_pages.lock().unwrap();
+        if let Some(max) = self.config.max_pages_per_brand {
+            if brand_pages.get(brand).is_some_and(|count| *count >= max) {
+                return Err(Exceeded::BrandPages);
+            }
+        }
+        if let Some(max) = self.config.max_pages {
+            if self.pages.fetch_add(1, Ordering::SeqCst) >= max {
+                self.exhausted.store(true, Ordering::SeqCst);
+                return Err(Exceeded::Pages);
+            }
+        } else {
+            self.pages.fetch_add(1, Ordering::SeqCst);
+        }
+        if self.config.max_pages_per_brand.is_some() {
+            *brand_pages.entry(brand.to_owned()).or_insert(0) += 1;
+        }
+        Ok(())
+    }
+
+    pub fn check_depth(&self, depth: u32) -> Result<(), Exceeded> {
+        match self.config.max_pagination_depth {
+            Some(max) if depth > max => Err(Exceeded::Depth),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn add_bytes(&self, n: usize) {
+        self.bytes.fetch_add(n as u64, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Budget, Exceeded};
+    use crate::config::BudgetConfig;
+
+    #[test]
+    fn test_budget() {
+        let budget = Budget::new(&BudgetConfig {
+            max_pages: Some(3),
+            max_pages_per_brand: Some(2),
+            max_pagination_depth: Some(1),
+            max_bytes: Some(100),
+            ..BudgetConfig::default()
+        });
+        assert_eq!(budget.acquire_page("honda"), Ok(()));
+        assert_eq!(budget.acquire_page("honda"), Ok(()));
+        assert_eq!(budget.acquire_page("honda"), Err(Exceeded::BrandPages));
+        assert!(!budget.is_exhausted());
+        assert_eq!(budget.acquire_page("ducati"), Ok(()));
+        assert_eq!(budget.acquire_page("ducati"), Err(Exceeded::Pages));
+        assert!(budget.is_exhausted());
+        assert_eq!(budget.brand_pages.lock().unwrap()["ducati"], 1);
+        assert_eq!(budget.check_depth(1), Ok(()));
+        assert_eq!(budget.check_depth(2), Err(Exceeded::Depth));
+
+        let budget = Budget::new(&BudgetConfig {
+            max_bytes: Some(100),
+            ..BudgetConfig::default()
+        });
+        budget.add_bytes(120);
+        assert_eq!(budget.acquire_page("honda"), Err(Exceeded::Bytes));
+    }
+}
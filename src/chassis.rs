@@ -0,0 +1,438 @@
+use regex::{Captures, Regex};
+use serde::Serialize;
+use std::collections::HashMap;
+
+// Keys of the rows read, matched case-insensitively. Tyres are sometimes listed in a single row,
+// as in "Front: 120/70 ZR17; Rear: 160/60 ZR17".
+static TYRE_KEYS: &str = r"(?i)^(front|rear)?\s*(tyres?|tires?)(\s+size)?$";
+static BRAKE_KEYS: &str = r"(?i)^(front|rear)\s+brakes?$";
+static SUSPENSION_KEYS: &str = r"(?i)^(front|rear)\s+suspension$";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Construction {
+    Radial,
+    Bias,
+    BeltedBias,
+}
+
+// A tyre size such as "120/70 ZR17 (58W)" or "3.00-21". Metric sizes have `width_mm` and `aspect`,
+// inch sizes `width_in`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Tyre {
+    raw: String,
+    width_mm: Option<u16>,
+    width_in: Option<f64>,
+    aspect: Option<u16>,
+    construction: Option<Construction>,
+    rim: Option<u8>,
+    load_index: Option<u16>,
+    speed_rating: Option<String>,
+    tubeless: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BrakeKind {
+    Disc,
+    Drum,
+}
+
+// A brake setup such as "2x 320mm discs, 4 piston calipers, ABS". `abs` is None unless the text says.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Brakes {
+    raw: String,
+    kind: Option<BrakeKind>,
+    discs: Option<u8>,
+    diameter_mm: Option<u16>,
+    pistons: Option<u8>,
+    abs: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuspensionKind {
+    TelescopicFork,
+    InvertedFork,
+    Telelever,
+    Duolever,
+    Girder,
+    LeadingLink,
+    Monoshock,
+    TwinShock,
+}
+
+// A suspension such as "43mm inverted telescopic fork, 120mm travel".
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Suspension {
+    raw: String,
+    kind: Option<SuspensionKind>,
+    fork_diameter_mm: Option<u16>,
+    travel_mm: Option<u16>,
+}
+
+// The tyres, brakes and suspension of a spec, read from their rows.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Chassis {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    front_tyre: Option<Tyre>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rear_tyre: Option<Tyre>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    front_brakes: Option<Brakes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rear_brakes: Option<Brakes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    front_suspension: Option<Suspension>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rear_suspension: Option<Suspension>,
+}
+
+// The compiled patterns of the chassis parsers.
+pub struct ChassisParser {
+    tyre_keys: Regex,
+    brake_keys: Regex,
+    suspension_keys: Regex,
+    metric: Regex,
+    inch: Regex,
+    tubeless: Regex,
+    tube_type: Regex,
+    drum: Regex,
+    disc: Regex,
+    disc_times: Regex,
+    disc_words: Regex,
+    diameter: Regex,
+    pistons: Regex,
+    no_abs: Regex,
+    abs: Regex,
+    fork: Regex,
+    travel: Regex,
+    travel_in: Regex,
+}
+
+impl Default for ChassisParser {
+    fn default() -> Self {
+        let re = |s: &str| Regex::new(s).unwrap();
+        Self {
+            tyre_keys: re(TYRE_KEYS),
+            brake_keys: re(BRAKE_KEYS),
+            suspension_keys: re(SUSPENSION_KEYS),
+            // 120/70 zr17, 130/90-16 67h, 180/55-zr17 m/c (73w)
+            metric: re(r"\b(\d{2,3})\s*/\s*(\d{2,3})\s*(-?\s*(?:[zvh]?r|b)|-)?\s*-?\s*(\d{2})\b(?:\s*(?:m/c)?\s*\(?(\d{2,3})\s*([a-z])\)?)?"),
+            // 3.00-21 51p, 4.10 h18, 2.75x18
+            inch: re(r"\b(\d{1,2}\.\d{2})\s*(-|x|[a-z])?\s*(\d{2})\b(?:\s*\(?(\d{2,3})\s*([a-z])\)?)?"),
+            tubeless: re(r"\btubeless\b|\btl\b"),
+            tube_type: re(r"\btube[- ]?type\b|\btt\b"),
+            drum: re(r"\bdrums?\b"),
+            disc: re(r"\b(discs?|disks?|rotors?)\b"),
+            disc_times: re(r"\b(\d)\s*x\s*[øØ]?\s*\d{3}"),
+            disc_words: re(r"\b(single|dual|double|twin|triple)\b[- ]?([a-z]*)"),
+            diameter: re(r"(\d{3})\s*mm\b"),
+            pistons: re(r"\b(\d|one|two|three|four|six|eight|single|dual|twin|double)[- ]?(pistons?|pots?)\b"),
+            no_abs: re(r"\b(non|without|no)[- ]abs\b"),
+            abs: re(r"\babs\b|\banti[- ]?lock"),
+            fork: re(r"(?:[øØ]\s*)?(\d{2})\s*mm\s*(?:[a-z-]+\s+){0,3}?(?:forks?|stanchions?|tubes?)\b"),
+            travel: re(r"(\d{2,3})(?:\.\d)?\s*mm\s*(?:\([^)]*\)\s*)?(?:of\s+)?(?:wheel\s+)?travel|travel\s*(?:of\s*)?:?\s*(\d{2,3})\s*mm"),
+            travel_in: re(r#"(\d+(?:\.\d+)?)\s*(?:in\.?|inch(?:es)?|")\s*(?:of\s+)?(?:wheel\s+)?travel"#),
+        }
+    }
+}
+
+impl ChassisParser {
+    // The chassis of a spec, None if it has none of the rows.
+    pub fn parse(&self, specs: &HashMap<String, Vec<String>>) -> Option<Chassis> {
+        let mut chassis = Chassis::default();
+        let mut found = false;
+        // A row of both tyres is read before the rows of one, which win over it whatever the order of the map.
+        let mut rows: Vec<(&String, &Vec<String>)> = specs.iter().collect();
+        rows.sort_by_key(|(key, _)| (self.tyre_keys.captures(key).is_none_or(|c| c.get(1).is_some()), key.as_str()));
+        for (key, values) in rows {
+            let value = match values.first() {
+                Some(value) => value,
+                None => continue,
+            };
+            let front = || key.to_lowercase().starts_with("front");
+            if let Some(c) = self.tyre_keys.captures(key) {
+                found = true;
+                if c.get(1).is_some() {
+                    let tyre = self.tyre(value, 0);
+                    if front() {
+                        chassis.front_tyre = tyre
+                    } else {
+                        chassis.rear_tyre = tyre
+                    }
+                } else {
+                    // A row of both tyres lists the front one first.
+                    chassis.front_tyre = self.tyre(value, 0);
+                    chassis.rear_tyre = self.tyre(value, 1);
+                }
+            } else if self.brake_keys.is_match(key) {
+                found = true;
+                let brakes = Some(self.brakes(value));
+                if front() {
+                    chassis.front_brakes = brakes
+                } else {
+                    chassis.rear_brakes = brakes
+                }
+            } else if self.suspension_keys.is_match(key) {
+                found = true;
+                let suspension = Some(self.suspension(value));
+                if front() {
+                    chassis.front_suspension = suspension
+                } else {
+                    chassis.rear_suspension = suspension
+                }
+            }
+        }
+        found.then_some(chassis)
+    }
+
+    // The `nth` tyre size of `raw`, None if there is no such size.
+    fn tyre(&self, raw: &str, nth: usize) -> Option<Tyre> {
+        let text = raw.to_lowercase();
+        let mut sizes: Vec<(usize, Tyre)> = self.metric.captures_iter(&text).map(|c| (c.get(0).unwrap().start(), metric_tyre(raw, &c))).collect();
+        sizes.extend(self.inch.captures_iter(&text).map(|c| (c.get(0).unwrap().start(), inch_tyre(raw, &c))));
+        sizes.sort_by_key(|(at, _)| *at);
+        // Tube type or tubeless is written after the size it belongs to, before the next size.
+        let end = sizes.get(nth + 1).map_or(text.len(), |(at, _)| *at);
+        let (at, mut tyre) = sizes.into_iter().nth(nth)?;
+        let rest = &text[at..end];
+        tyre.tubeless = if self.tubeless.is_match(rest) {
+            Some(true)
+        } else if self.tube_type.is_match(rest) {
+            Some(false)
+        } else {
+            None
+        };
+        Some(tyre)
+    }
+
+    fn brakes(&self, raw: &str) -> Brakes {
+        let text = raw.to_lowercase();
+        let kind = if self.drum.is_match(&text) {
+            Some(BrakeKind::Drum)
+        } else if self.disc.is_match(&text) {
+            Some(BrakeKind::Disc)
+        } else {
+            None
+        };
+        let discs = self
+            .disc_times
+            .captures(&text)
+            .and_then(|c| count(&c[1]))
+            .or_else(|| {
+                // "Twin" and "dual" also describe calipers, as in "twin-piston".
+                self.disc_words
+                    .captures_iter(&text)
+                    .find(|c| !(c[2].starts_with("piston") || c[2].starts_with("pot") || c[2].starts_with("action")))
+                    .and_then(|c| count(&c[1]))
+            })
+            .or_else(|| self.disc.captures(&text).filter(|c| matches!(&c[1], "disc" | "disk" | "rotor")).map(|_| 1))
+            .filter(|_| kind == Some(BrakeKind::Disc));
+        let abs = if self.no_abs.is_match(&text) {
+            Some(false)
+        } else if self.abs.is_match(&text) {
+            Some(true)
+        } else {
+            None
+        };
+        Brakes {
+            raw: raw.to_owned(),
+            kind,
+            discs,
+            diameter_mm: self.diameter.captures(&text).and_then(|c| c[1].parse().ok()).filter(|d| (100..=420).contains(d)),
+            pistons: self.pistons.captures(&text).and_then(|c| count(&c[1])),
+            abs,
+        }
+    }
+
+    fn suspension(&self, raw: &str) -> Suspension {
+        let text = raw.to_lowercase();
+        let has = |words: &[&str]| words.iter().any(|w| text.contains(w));
+        let kind = if has(&["telelever"]) {
+            Some(SuspensionKind::Telelever)
+        } else if has(&["duolever"]) {
+            Some(SuspensionKind::Duolever)
+        } else if has(&["girder"]) {
+            Some(SuspensionKind::Girder)
+        } else if has(&["leading link", "leading-link", "earles"]) {
+            Some(SuspensionKind::LeadingLink)
+        } else if has(&["inverted", "upside down", "upside-down", "usd"]) {
+            Some(SuspensionKind::InvertedFork)
+        } else if has(&["fork", "telescopic"]) {
+            Some(SuspensionKind::TelescopicFork)
+        } else if has(&[
+            "mono",
+            "single shock",
+            "pro-link",
+            "pro link",
+            "uni-trak",
+            "full floater",
+            "paralever",
+            "cantilever",
+            "central shock",
+            "single damper",
+        ]) {
+            Some(SuspensionKind::Monoshock)
+        } else if has(&["twin shock", "dual shock", "two shock", "shocks", "dampers"]) {
+            Some(SuspensionKind::TwinShock)
+        } else {
+            None
+        };
+        let travel = self
+            .travel
+            .captures(&text)
+            .and_then(|c| c.get(1).or_else(|| c.get(2)))
+            .and_then(|m| m.as_str().parse().ok())
+            .or_else(|| self.travel_in.captures(&text).and_then(|c| c[1].parse::<f64>().ok()).map(|inches| (inches * 25.4).round() as u16));
+        Suspension {
+            raw: raw.to_owned(),
+            kind,
+            fork_diameter_mm: self.fork.captures(&text).and_then(|c| c[1].parse().ok()).filter(|d| (25..=60).contains(d)),
+            travel_mm: travel,
+        }
+    }
+}
+
+fn metric_tyre(raw: &str, c: &Captures) -> Tyre {
+    let construction = c.get(3).map(|m| m.as_str().trim_start_matches(['-', ' '])).map(|m| match m {
+        "" => Construction::Bias,
+        "b" => Construction::BeltedBias,
+        _ => Construction::Radial,
+    });
+    // Speed rated radials such as ZR carry the rating in the construction.
+    let speed = c
+        .get(6)
+        .map(|m| m.as_str().to_uppercase())
+        .or_else(|| c.get(3).map(|m| m.as_str().trim_start_matches(['-', ' '])).filter(|m| m.len() == 2).map(|m| m[..1].to_uppercase()));
+    Tyre {
+        raw: raw.to_owned(),
+        width_mm: c[1].parse().ok(),
+        width_in: None,
+        aspect: c[2].parse().ok(),
+        construction,
+        rim: c[4].parse().ok(),
+        load_index: c.get(5).and_then(|m| m.as_str().parse().ok()),
+        speed_rating: speed,
+        tubeless: None,
+    }
+}
+
+fn inch_tyre(raw: &str, c: &Captures) -> Tyre {
+    // Inch sizes are bias ply unless written as width by rim.
+    let construction = c.get(2).filter(|m| m.as_str() != "x").map(|_| Construction::Bias);
+    // A letter between width and rim is the speed rating, as in "4.10 H18".
+    let speed = c.get(5).or_else(|| c.get(2).filter(|m| m.as_str() != "-" && m.as_str() != "x")).map(|m| m.as_str().to_uppercase());
+    Tyre {
+        raw: raw.to_owned(),
+        width_mm: None,
+        width_in: c[1].parse().ok(),
+        aspect: None,
+        construction,
+        rim: c[3].parse().ok(),
+        load_index: c.get(4).and_then(|m| m.as_str().parse().ok()),
+        speed_rating: speed,
+        tubeless: None,
+    }
+}
+
+fn count(word: &str) -> Option<u8> {
+    match word {
+        "one" | "single" => Some(1),
+        "two" | "dual" | "twin" | "double" => Some(2),
+        "three" | "triple" => Some(3),
+        "four" => Some(4),
+        "six" => Some(6),
+        "eight" => Some(8),
+        n => n.parse().ok().filter(|n| *n > 0),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BrakeKind, Brakes, Chassis, ChassisParser, Construction, SuspensionKind};
+    use std::collections::HashMap;
+
+    fn parse(rows: &[(&str, &str)]) -> Option<Chassis> {
+        let specs: HashMap<String, Vec<String>> = rows.iter().map(|(k, v)| (k.to_string(), vec![v.to_string()])).collect();
+        ChassisParser::default().parse(&specs)
+    }
+
+    #[test]
+    fn test_tyres() {
+        let chassis = parse(&[("Front Tyre", "120/70 ZR17"), ("Rear Tyre", "180/55-ZR17 M/C (73W) TL")]).unwrap();
+        let front = chassis.front_tyre.unwrap();
+        assert_eq!(
+            (front.width_mm, front.aspect, front.construction, front.rim),
+            (Some(120), Some(70), Some(Construction::Radial), Some(17))
+        );
+        assert_eq!((front.speed_rating.as_deref(), front.tubeless, front.raw.as_str()), (Some("Z"), None, "120/70 ZR17"));
+        let rear = chassis.rear_tyre.unwrap();
+        assert_eq!(
+            (rear.width_mm, rear.rim, rear.load_index, rear.speed_rating.as_deref(), rear.tubeless),
+            (Some(180), Some(17), Some(73), Some("W"), Some(true))
+        );
+        let chassis = parse(&[("Tyres", "Front: 3.00-21 51P tube type; Rear: 130/90-16 67H")]).unwrap();
+        let front = chassis.front_tyre.unwrap();
+        assert_eq!(
+            (front.width_in, front.construction, front.rim, front.load_index),
+            (Some(3.0), Some(Construction::Bias), Some(21), Some(51))
+        );
+        assert_eq!((front.speed_rating.as_deref(), front.tubeless), (Some("P"), Some(false)));
+        let rear = chassis.rear_tyre.unwrap();
+        assert_eq!(
+            (rear.width_mm, rear.aspect, rear.construction, rear.rim, rear.speed_rating.as_deref()),
+            (Some(130), Some(90), Some(Construction::Bias), Some(16), Some("H"))
+        );
+        assert_eq!(parse(&[("Rear Tyre", "Not available")]).unwrap().rear_tyre, None);
+        // Parsed many times as every map iterates in its own order.
+        for _ in 0..20 {
+            let chassis = parse(&[("Tyres", "100/90-19, 130/80-17"), ("Front Tyre", "110/80 R19")]).unwrap();
+            assert_eq!(chassis.front_tyre.unwrap().width_mm, Some(110));
+            assert_eq!(chassis.rear_tyre.unwrap().width_mm, Some(130));
+        }
+    }
+
+    #[test]
+    fn test_brakes() {
+        let chassis = parse(&[("Front Brakes", "2x 320mm discs 4 piston calipers"), ("Rear Brakes", "Single 220mm disc, 1-piston caliper, ABS")]).unwrap();
+        assert_eq!(
+            chassis.front_brakes.unwrap(),
+            Brakes {
+                raw: "2x 320mm discs 4 piston calipers".to_owned(),
+                kind: Some(BrakeKind::Disc),
+                discs: Some(2),
+                diameter_mm: Some(320),
+                pistons: Some(4),
+                abs: None,
+            }
+        );
+        let rear = chassis.rear_brakes.unwrap();
+        assert_eq!((rear.discs, rear.diameter_mm, rear.pistons, rear.abs), (Some(1), Some(220), Some(1), Some(true)));
+        let front = parse(&[("Front Brakes", "Dual semi-floating 310 mm discs, radial mounted twin-piston calipers, non-ABS")])
+            .unwrap()
+            .front_brakes
+            .unwrap();
+        assert_eq!((front.discs, front.diameter_mm, front.pistons, front.abs), (Some(2), Some(310), Some(2), Some(false)));
+        let rear = parse(&[("Rear Brakes", "Drum 130 mm")]).unwrap().rear_brakes.unwrap();
+        assert_eq!((rear.kind, rear.discs, rear.diameter_mm), (Some(BrakeKind::Drum), None, Some(130)));
+    }
+
+    #[test]
+    fn test_suspension() {
+        let chassis = parse(&[
+            ("Front Suspension", "43mm inverted telescopic fork, 120mm travel"),
+            ("Rear Suspension", "Pro-Link single shock, 5-step preload adjustable, 130 mm wheel travel"),
+        ])
+        .unwrap();
+        let front = chassis.front_suspension.unwrap();
+        assert_eq!((front.kind, front.fork_diameter_mm, front.travel_mm), (Some(SuspensionKind::InvertedFork), Some(43), Some(120)));
+        let rear = chassis.rear_suspension.unwrap();
+        assert_eq!((rear.kind, rear.fork_diameter_mm, rear.travel_mm), (Some(SuspensionKind::Monoshock), None, Some(130)));
+        let rear = parse(&[("Rear Suspension", "Twin shocks, 5-way preload adjustable, 4.1 in travel")]).unwrap().rear_suspension.unwrap();
+        assert_eq!((rear.kind, rear.travel_mm), (Some(SuspensionKind::TwinShock), Some(104)));
+        let front = parse(&[("Front Suspension", "Telelever; 35 mm central spring strut")]).unwrap().front_suspension.unwrap();
+        assert_eq!((front.kind, front.fork_diameter_mm), (Some(SuspensionKind::Telelever), None));
+        assert_eq!(parse(&[("Frame", "Steel")]), None);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::config::ConfigError;
+use crate::diagnostic::{DiagnosticKind, Extracted};
+use crate::profile::ClassifyRules;
+use crate::result::Spec;
+use regex::{Regex, RegexBuilder};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PageClass {
+    Spec,
+    // An index, gallery or history page linked like a model page. `reason` is one of a few fixed
+    // strings, counted in the run report, `detail` tells what was found on the page.
+    NotSpec { reason: &'static str, detail: String },
+}
+
+impl fmt::Display for PageClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PageClass::Spec => write!(f, "spec page"),
+            PageClass::NotSpec { reason, detail } => write!(f, "{} ({})", reason, detail),
+        }
+    }
+}
+
+// Tells spec pages from the other pages a site links like models, by the spec rows extracted from
+// the page: how many there are, whether their keys look like specs and how many rows were dropped
+// for holding links.
+pub struct Classifier {
+    min_rows: usize,
+    expected_keys: Vec<Regex>,
+    min_expected: usize,
+    max_link_share: f64,
+}
+
+impl Classifier {
+    pub fn new(rules: &ClassifyRules) -> std::result::Result<Self, ConfigError> {
+        let mut expected_keys = Vec::new();
+        for (i, pattern) in rules.expected_keys.iter().enumerate() {
+            let re = RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| ConfigError::new(&format!("profile.classify.expected_keys[{}]", i), e.to_string()))?;
+            expected_keys.push(re);
+        }
+        if rules.min_expected > expected_keys.len() {
+            return Err(ConfigError::new("profile.classify.min_expected", "must not exceed the number of expected_keys"));
+        }
+        if !(0.0..=1.0).contains(&rules.max_link_share) {
+            return Err(ConfigError::new("profile.classify.max_link_share", "must be between 0 and 1"));
+        }
+        Ok(Self {
+            min_rows: rules.min_rows,
+            expected_keys,
+            min_expected: rules.min_expected,
+            max_link_share: rules.max_link_share,
+        })
+    }
+
+    pub fn classify(&self, page: &Extracted<Vec<Spec>>) -> PageClass {
+        let rows = page.value.iter().map(|s| s.get_specs().values().map(Vec::len).sum::<usize>()).max().unwrap_or(0);
+        let links = page.diagnostics.iter().filter(|d| d.get_kind() == DiagnosticKind::ExcludedRow).count();
+        if links > 0 && links as f64 / (links + rows) as f64 > self.max_link_share {
+            return PageClass::NotSpec {
+                reason: "mostly links",
+                detail: format!("{} of {} rows hold links", links, links + rows),
+            };
+        }
+        if rows < self.min_rows {
+            return PageClass::NotSpec {
+                reason: "too few rows",
+                detail: format!("{} spec rows, at least {} expected", rows, self.min_rows),
+            };
+        }
+        let expected = self.expected_keys.iter().filter(|re| page.value.iter().any(|s| s.get_specs().keys().any(|k| re.is_match(k)))).count();
+        if expected < self.min_expected {
+            return PageClass::NotSpec {
+                reason: "missing expected keys",
+                detail: format!("{} of the expected keys, at least {} expected", expected, self.min_expected),
+            };
+        }
+        PageClass::Spec
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Classifier, PageClass};
+    use crate::profile::{ClassifyRules, Extractor};
+
+    fn classify(classifier: &Classifier, html: &str) -> PageClass {
+        let page = Extractor::default().specs(html, "Honda", "CB 500", "2013", "https://a/model/honda/cb500.html");
+        classifier.classify(&page)
+    }
+
+    #[test]
+    fn test_classify() {
+        let rules = ClassifyRules {
+            min_rows: 3,
+            expected_keys: vec!["^engine".to_owned(), "power".to_owned(), "weight".to_owned()],
+            min_expected: 2,
+            max_link_share: 0.5,
+        };
+        let classifier = Classifier::new(&rules).unwrap();
+        let spec = "<table><tr><td>Engine</td><td>Twin</td></tr><tr><td>Max Power</td><td>35 kW</td></tr><tr><td>Seat</td><td>790 mm</td></tr></table>";
+        assert_eq!(classify(&classifier, spec), PageClass::Spec);
+        let gallery = r#"<table><tr><td><a href="/g/1.html">Red</a></td><td>2013</td></tr><tr><td><a href="/g/2.html">Blue</a></td><td>2014</td></tr>
+            <tr><td>Engine</td><td>Twin</td></tr></table>"#;
+        assert_eq!(classify(&classifier, gallery).to_string(), "mostly links (2 of 3 rows hold links)");
+        let short = "<table><tr><td>Engine</td><td>Twin</td></tr></table>";
+        assert_eq!(classify(&classifier, short).to_string(), "too few rows (1 spec rows, at least 3 expected)");
+        let history = "<table><tr><td>1993</td><td>Monster</td></tr><tr><td>1994</td><td>916</td></tr><tr><td>Engine</td><td>Twin</td></tr></table>";
+        assert!(matches!(classify(&classifier, history), PageClass::NotSpec { reason: "missing expected keys", .. }));
+
+        let err = Classifier::new(&ClassifyRules { min_expected: 4, ..rules.clone() }).err().unwrap();
+        assert!(err.to_string().contains("`profile.classify.min_expected`"), "{}", err);
+        let err = Classifier::new(&ClassifyRules {
+            expected_keys: vec!["(".to_owned()],
+            min_expected: 0,
+            ..rules
+        })
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("`profile.classify.expected_keys[0]`"), "{}", err);
+    }
+}
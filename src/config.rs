@@ -0,0 +1,718 @@
+use crate::http::{BASE_URL, DEFAULT_HEADERS};
+use crate::profile::{Extractor, SiteProfile};
+use crate::result::Result;
+use crate::scope::Scope;
+use crate::site::host;
+use reqwest::header::{HeaderName, HeaderValue};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+use toml::value::{Table, Value};
+use tracing_subscriber::EnvFilter;
+
+// Environment variables which are still honoured and the config keys they override.
+static ENV_OVERRIDES: &[(&str, &str)] = &[
+    ("MONGO_URI", "mongo.uri"),
+    ("MONGO_DB", "mongo.db"),
+    ("MONGO_DATA_COLL", "mongo.data_coll"),
+    ("MONGO_LOG_COLL", "mongo.log_coll"),
+    ("NUM_OF_HTTP_CONN", "http.num_conns"),
+];
+
+pub static CONFIG_ENV: &str = "MOTOSPEC_CONFIG";
+
+#[derive(Debug)]
+pub struct ConfigError {
+    key: String,
+    message: String,
+}
+
+impl ConfigError {
+    pub fn new(key: &str, message: impl Into<String>) -> Self {
+        Self {
+            key: key.to_owned(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.key.is_empty() {
+            write!(f, "invalid config: {}", self.message)
+        } else {
+            write!(f, "invalid config key `{}`: {}", self.key, self.message)
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MongoConfig {
+    pub uri: String,
+    pub db: String,
+    pub data_coll: String,
+    pub log_coll: String,
+}
+
+impl Default for MongoConfig {
+    fn default() -> Self {
+        Self {
+            uri: String::new(),
+            db: "motospec".to_owned(),
+            data_coll: "spec".to_owned(),
+            log_coll: "log".to_owned(),
+        }
+    }
+}
+
+// Specs and log entries are written in batches of up to `max_items`, buffered entries are written
+// at least every `flush_interval_ms`. `max_items = 1` writes every item directly and needs no timer.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatchConfig {
+    pub max_items: usize,
+    pub flush_interval_ms: u64,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_items: 100,
+            flush_interval_ms: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub num_conns: usize,
+    pub timeout_secs: u64,
+    pub connect_timeout_secs: u64,
+    // Maximum number of requests started per second, 0 disables rate limiting.
+    pub requests_per_sec: f64,
+    // Merged over DEFAULT_HEADERS, an empty value removes a default header.
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            num_conns: 32,
+            timeout_secs: 10,
+            connect_timeout_secs: 10,
+            requests_per_sec: 0.0,
+            headers: BTreeMap::new(),
+        }
+    }
+}
+
+impl HttpConfig {
+    pub fn get_headers(&self) -> Vec<(String, String)> {
+        let mut headers: Vec<(String, String)> = DEFAULT_HEADERS
+            .iter()
+            .filter(|(k, _)| !self.headers.keys().any(|o| o.eq_ignore_ascii_case(k)))
+            .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+            .collect();
+        headers.extend(self.headers.iter().filter(|(_, v)| !v.is_empty()).map(|(k, v)| (k.to_owned(), v.to_owned())));
+        headers
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    // Attempts per request, including the first one.
+    pub max_attempts: u32,
+    pub backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            backoff_ms: 500,
+            max_backoff_ms: 10_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScopeConfig {
+    pub base_url: String,
+    // Brands to crawl, empty means all of them.
+    pub brands: Vec<String>,
+    pub exclude_brands: Vec<String>,
+    // Alternative spellings mapped to the brand name used in the filters.
+    pub brand_aliases: BTreeMap<String, String>,
+    pub min_year: Option<i32>,
+    pub max_year: Option<i32>,
+    // Whether models without a recognizable year pass a year filter.
+    pub include_unknown_years: bool,
+    // Regular expressions a model page url must match.
+    pub include_urls: Vec<String>,
+    // Regular expressions excluding any brand, listing or model page url.
+    pub exclude_urls: Vec<String>,
+}
+
+impl Default for ScopeConfig {
+    fn default() -> Self {
+        Self {
+            base_url: BASE_URL.to_owned(),
+            brands: Vec::new(),
+            exclude_brands: Vec::new(),
+            brand_aliases: BTreeMap::new(),
+            min_year: None,
+            max_year: None,
+            include_unknown_years: true,
+            include_urls: Vec::new(),
+            exclude_urls: Vec::new(),
+        }
+    }
+}
+
+// Every limit is optional, an unset limit never stops the crawl.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BudgetConfig {
+    pub max_pages: Option<u64>,
+    pub max_pages_per_brand: Option<u64>,
+    // Number of "Next" links followed from the first listing page of a brand.
+    pub max_pagination_depth: Option<u32>,
+    pub max_duration_secs: Option<u64>,
+    pub max_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrawlOrder {
+    // Oldest job first: all brands, then their listing pages, then their models.
+    BreadthFirst,
+    // Newest job first: the pages of a brand are finished before the next brand is started.
+    DepthFirst,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CrawlConfig {
+    pub workers: usize,
+    // Jobs the queue holds at most, seeding pauses while it is full.
+    pub queue_capacity: usize,
+    pub order: CrawlOrder,
+}
+
+impl Default for CrawlConfig {
+    fn default() -> Self {
+        Self {
+            workers: 32,
+            queue_capacity: 1000,
+            order: CrawlOrder::BreadthFirst,
+        }
+    }
+}
+
+// How urls are normalized before checking whether they were already visited.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DedupConfig {
+    pub ignore_path_case: bool,
+    pub ignore_query: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownConfig {
+    // How long in-flight requests may run after SIGINT/SIGTERM before they are abandoned.
+    pub grace_secs: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self { grace_secs: 30 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProgressMode {
+    // A status line when stderr is a terminal, JSON lines otherwise.
+    Auto,
+    Terminal,
+    Json,
+    Off,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProgressConfig {
+    pub mode: ProgressMode,
+    pub interval_secs: u64,
+}
+
+impl Default for ProgressConfig {
+    fn default() -> Self {
+        Self {
+            mode: ProgressMode::Auto,
+            interval_secs: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    // Address of the Prometheus `/metrics` endpoint.
+    pub listen: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: "127.0.0.1:9898".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    Pretty,
+    Json,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TracingConfig {
+    // Filter directives in the RUST_LOG syntax, e.g. "info" or "warn,moto_spec=debug".
+    pub level: String,
+    pub format: LogFormat,
+}
+
+impl Default for TracingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_owned(),
+            format: LogFormat::Pretty,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReportConfig {
+    pub enabled: bool,
+    // Also read at startup to compare with the previous run, empty to skip the file.
+    pub json_path: String,
+    pub html_path: String,
+    // Specs with fewer fields are listed in the report.
+    pub few_fields: usize,
+    pub top_keys: usize,
+}
+
+impl Default for ReportConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            json_path: "report.json".to_owned(),
+            html_path: "report.html".to_owned(),
+            few_fields: 5,
+            top_keys: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImagesConfig {
+    // Whether the images found on model pages are downloaded, their urls are stored either way.
+    pub download: bool,
+    pub dir: String,
+    // Larger files are left out of the archive.
+    pub max_bytes: usize,
+}
+
+impl Default for ImagesConfig {
+    fn default() -> Self {
+        Self {
+            download: false,
+            dir: "images".to_owned(),
+            max_bytes: 5 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SanityAction {
+    // Stop the crawl like a SIGTERM and exit with an error.
+    Abort,
+    // Log the failed check and carry on.
+    Alert,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SanityConfig {
+    pub min_brands: usize,
+    pub min_fields: usize,
+    // Keys every spec is expected to have, compared case-insensitively.
+    pub core_keys: Vec<String>,
+    // Ratios are only checked once this many specs were extracted.
+    pub min_sample: u64,
+    pub max_empty_ratio: f64,
+    // Specs with fewer than `min_fields` fields or without a core key.
+    pub max_poor_ratio: f64,
+    pub action: SanityAction,
+    // Offending pages are saved here for inspection, empty to not save them.
+    pub dump_dir: String,
+    pub max_saved_pages: usize,
+}
+
+impl Default for SanityConfig {
+    fn default() -> Self {
+        Self {
+            min_brands: 20,
+            min_fields: 5,
+            core_keys: vec!["Engine".to_owned()],
+            min_sample: 50,
+            max_empty_ratio: 0.2,
+            max_poor_ratio: 0.5,
+            action: SanityAction::Abort,
+            dump_dir: "sanity".to_owned(),
+            max_saved_pages: 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SiteConfig {
+    // Path of the extraction rules, see profiles/motorcyclespecs.toml; the built-in rules if empty.
+    pub profile: String,
+    // Further sites crawled after motorcyclespecs.co.za.
+    pub sources: Vec<SourceConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SourceConfig {
+    pub name: String,
+    pub index_url: String,
+    pub profile: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub mongo: MongoConfig,
+    pub batch: BatchConfig,
+    pub http: HttpConfig,
+    pub retry: RetryConfig,
+    pub scope: ScopeConfig,
+    pub budget: BudgetConfig,
+    pub shutdown: ShutdownConfig,
+    pub dedup: DedupConfig,
+    pub crawl: CrawlConfig,
+    pub progress: ProgressConfig,
+    pub metrics: MetricsConfig,
+    pub tracing: TracingConfig,
+    pub report: ReportConfig,
+    pub sanity: SanityConfig,
+    pub site: SiteConfig,
+    pub images: ImagesConfig,
+}
+
+impl Config {
+    // Loads the config file given by `--config <path>` or MOTOSPEC_CONFIG, then applies the
+    // legacy environment variables and finally `--set key=value` arguments.
+    pub fn load(args: &[String]) -> Result<Self> {
+        let args = Args::parse(args)?;
+        let path = args.config.clone().or_else(|| env::var(CONFIG_ENV).ok());
+        let content = match path {
+            Some(path) => Some(fs::read_to_string(&path).map_err(|e| ConfigError::new("", format!("failed to read {}: {}", path, e)))?),
+            None => None,
+        };
+        Self::build(content.as_deref(), |k| env::var(k).ok(), &args)
+    }
+
+    // The config of `load` from the content of the config file, the environment and the arguments.
+    fn build(content: Option<&str>, env: impl Fn(&str) -> Option<String>, args: &Args) -> Result<Self> {
+        let mut value = match content {
+            Some(content) => parse(content)?,
+            None => Value::Table(Table::new()),
+        };
+        apply_env(&mut value, env)?;
+        for (key, val) in &args.overrides {
+            set_key(&mut value, key, parse_value(val))?;
+        }
+        let config = from_value(value)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> std::result::Result<(), ConfigError> {
+        if self.mongo.uri.trim().is_empty() {
+            return Err(ConfigError::new("mongo.uri", "must be set"));
+        }
+        for (key, val) in &[("mongo.db", &self.mongo.db), ("mongo.data_coll", &self.mongo.data_coll), ("mongo.log_coll", &self.mongo.log_coll)] {
+            if val.trim().is_empty() {
+                return Err(ConfigError::new(key, "must not be empty"));
+            }
+        }
+        if self.batch.max_items == 0 {
+            return Err(ConfigError::new("batch.max_items", "must be greater than 0"));
+        }
+        // Specs wait for their batch to be written, only the timer writes a batch that does not fill up.
+        if self.batch.max_items > 1 && self.batch.flush_interval_ms == 0 {
+            return Err(ConfigError::new("batch.flush_interval_ms", "must be greater than 0 when max_items is above 1"));
+        }
+        if self.http.num_conns == 0 {
+            return Err(ConfigError::new("http.num_conns", "must be greater than 0"));
+        }
+        if self.http.timeout_secs == 0 {
+            return Err(ConfigError::new("http.timeout_secs", "must be greater than 0"));
+        }
+        if self.http.connect_timeout_secs == 0 {
+            return Err(ConfigError::new("http.connect_timeout_secs", "must be greater than 0"));
+        }
+        if !self.http.requests_per_sec.is_finite() || self.http.requests_per_sec < 0.0 {
+            return Err(ConfigError::new("http.requests_per_sec", "must be a non-negative number"));
+        }
+        for (name, val) in &self.http.headers {
+            let key = format!("http.headers.{}", name);
+            HeaderName::from_str(name).map_err(|e| ConfigError::new(&key, e.to_string()))?;
+            HeaderValue::from_str(val).map_err(|e| ConfigError::new(&key, e.to_string()))?;
+        }
+        if self.retry.max_attempts == 0 {
+            return Err(ConfigError::new("retry.max_attempts", "must be at least 1"));
+        }
+        if self.retry.max_backoff_ms < self.retry.backoff_ms {
+            return Err(ConfigError::new("retry.max_backoff_ms", "must not be less than retry.backoff_ms"));
+        }
+        url::Url::parse(&self.scope.base_url).map_err(|e| ConfigError::new("scope.base_url", e.to_string()))?;
+        if let (Some(min), Some(max)) = (self.scope.min_year, self.scope.max_year) {
+            if min > max {
+                return Err(ConfigError::new("scope.max_year", "must not be less than scope.min_year"));
+            }
+        }
+        Scope::new(&self.scope)?;
+        Extractor::new(SiteProfile::load(&self.site.profile)?)?;
+        let mut names = vec!["motorcyclespecs".to_owned()];
+        let mut hosts = vec![host(&self.scope.base_url)];
+        for (i, source) in self.site.sources.iter().enumerate() {
+            let key = format!("site.sources[{}]", i);
+            if source.name.trim().is_empty() || names.contains(&source.name) {
+                return Err(ConfigError::new(&format!("{}.name", key), "must be set and unique"));
+            }
+            url::Url::parse(&source.index_url).map_err(|e| ConfigError::new(&format!("{}.index_url", key), e.to_string()))?;
+            // Pages are matched to their site by host.
+            if hosts.contains(&host(&source.index_url)) {
+                return Err(ConfigError::new(&format!("{}.index_url", key), "another site is crawled on this host"));
+            }
+            if source.profile.trim().is_empty() {
+                return Err(ConfigError::new(&format!("{}.profile", key), "must be set"));
+            }
+            Extractor::new(SiteProfile::load(&source.profile)?)?;
+            names.push(source.name.clone());
+            hosts.push(host(&source.index_url));
+        }
+        if self.crawl.workers == 0 {
+            return Err(ConfigError::new("crawl.workers", "must be greater than 0"));
+        }
+        if self.crawl.queue_capacity == 0 {
+            return Err(ConfigError::new("crawl.queue_capacity", "must be greater than 0"));
+        }
+        if self.progress.interval_secs == 0 {
+            return Err(ConfigError::new("progress.interval_secs", "must be greater than 0"));
+        }
+        for (key, val) in &[("sanity.max_empty_ratio", self.sanity.max_empty_ratio), ("sanity.max_poor_ratio", self.sanity.max_poor_ratio)] {
+            if !(0.0..=1.0).contains(val) {
+                return Err(ConfigError::new(key, "must be between 0 and 1"));
+            }
+        }
+        EnvFilter::try_new(&self.tracing.level).map_err(|e| ConfigError::new("tracing.level", e.to_string()))?;
+        if self.metrics.enabled {
+            self.metrics.listen.parse::<std::net::SocketAddr>().map_err(|e| ConfigError::new("metrics.listen", e.to_string()))?;
+        }
+        if self.images.download && self.images.dir.trim().is_empty() {
+            return Err(ConfigError::new("images.dir", "must be set to download images"));
+        }
+        if self.images.max_bytes == 0 {
+            return Err(ConfigError::new("images.max_bytes", "must be greater than 0"));
+        }
+        for (key, val) in &[
+            ("budget.max_pages", self.budget.max_pages),
+            ("budget.max_pages_per_brand", self.budget.max_pages_per_brand),
+            ("budget.max_duration_secs", self.budget.max_duration_secs),
+            ("budget.max_bytes", self.budget.max_bytes),
+        ] {
+            if *val == Some(0) {
+                return Err(ConfigError::new(key, "must be greater than 0, leave it unset for no limit"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Args {
+    config: Option<String>,
+    overrides: Vec<(String, String)>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> std::result::Result<Self, ConfigError> {
+        let mut parsed = Args::default();
+        let mut iter = args.iter().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "-c" | "--config" => parsed.config = Some(iter.next().ok_or_else(|| ConfigError::new("", format!("{} requires a path", arg)))?.to_owned()),
+                "--set" => {
+                    let kv = iter.next().ok_or_else(|| ConfigError::new("", "--set requires key=value"))?;
+                    parsed.overrides.push(split_override(kv)?);
+                }
+                _ => {
+                    if let Some(path) = arg.strip_prefix("--config=") {
+                        parsed.config = Some(path.to_owned());
+                    } else if let Some(kv) = arg.strip_prefix("--set=") {
+                        parsed.overrides.push(split_override(kv)?);
+                    } else {
+                        return Err(ConfigError::new("", format!("unknown argument {}", arg)));
+                    }
+                }
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+fn split_override(kv: &str) -> std::result::Result<(String, String), ConfigError> {
+    match kv.split_once('=') {
+        Some((k, v)) if !k.trim().is_empty() => Ok((k.trim().to_owned(), v.to_owned())),
+        _ => Err(ConfigError::new("", format!("override `{}` is not in key=value form", kv))),
+    }
+}
+
+fn parse(content: &str) -> std::result::Result<Value, ConfigError> {
+    content.parse::<Value>().map_err(|e| match e.line_col() {
+        Some((line, col)) => ConfigError::new("", format!("{} (line {}, column {})", e, line + 1, col + 1)),
+        None => ConfigError::new("", e.to_string()),
+    })
+}
+
+// Override values are parsed as TOML so numbers and booleans keep their type, anything else is a string.
+fn parse_value(raw: &str) -> Value {
+    match format!("v = {}", raw).parse::<Value>() {
+        Ok(Value::Table(mut t)) => t.remove("v").unwrap_or_else(|| Value::String(raw.to_owned())),
+        _ => Value::String(raw.to_owned()),
+    }
+}
+
+fn apply_env(value: &mut Value, lookup: impl Fn(&str) -> Option<String>) -> std::result::Result<(), ConfigError> {
+    for (var, key) in ENV_OVERRIDES {
+        if let Some(val) = lookup(var) {
+            set_key(value, key, parse_value(&val))?;
+        }
+    }
+    Ok(())
+}
+
+fn set_key(value: &mut Value, key: &str, val: Value) -> std::result::Result<(), ConfigError> {
+    let parts: Vec<&str> = key.split('.').collect();
+    let mut cur = value;
+    for (i, part) in parts.iter().enumerate() {
+        let table = match cur {
+            Value::Table(t) => t,
+            _ => return Err(ConfigError::new(&parts[..i].join("."), "is not a table")),
+        };
+        if i == parts.len() - 1 {
+            table.insert((*part).to_owned(), val);
+            return Ok(());
+        }
+        cur = table.entry((*part).to_owned()).or_insert_with(|| Value::Table(Table::new()));
+    }
+    Ok(())
+}
+
+fn from_value(value: Value) -> std::result::Result<Config, ConfigError> {
+    serde_path_to_error::deserialize(value).map_err(|e| {
+        let key = e.path().to_string();
+        ConfigError::new(if key == "." { "" } else { &key }, e.into_inner().to_string())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Args, Config};
+    use std::env;
+    use std::fs;
+
+    static SAMPLE: &str = r#"
+        [mongo]
+        uri = "mongodb://127.0.0.1"
+
+        [http]
+        num_conns = 8
+        requests_per_sec = 2.5
+
+        [http.headers]
+        User-Agent = "motospec"
+        Cookie = ""
+    "#;
+
+    fn load(content: &str, env: &[(&str, &str)], args: &[&str]) -> crate::result::Result<Config> {
+        let args = Args::parse(&args.iter().map(|s| s.to_string()).collect::<Vec<_>>())?;
+        Config::build(Some(content), |k| env.iter().find(|(e, _)| *e == k).map(|(_, v)| v.to_string()), &args)
+    }
+
+    #[test]
+    fn test_load_with_overrides() {
+        let config = load(SAMPLE, &[("NUM_OF_HTTP_CONN", "16")], &["moto_spec", "--set", "retry.max_attempts=3", "--set=mongo.db=bikes"]).unwrap();
+        assert_eq!(config.http.num_conns, 16);
+        assert_eq!(config.retry.max_attempts, 3);
+        assert_eq!(config.mongo.db, "bikes");
+        assert_eq!(config.mongo.log_coll, "log");
+        let headers = config.http.get_headers();
+        assert!(headers.iter().any(|(k, v)| k == "User-Agent" && v == "motospec"));
+        assert!(!headers.iter().any(|(k, _)| k == "Cookie"));
+    }
+
+    #[test]
+    fn test_load_file() {
+        let path = env::temp_dir().join(format!("motospec-config-{}.toml", std::process::id()));
+        fs::write(&path, SAMPLE).unwrap();
+        let args: Vec<String> = vec!["moto_spec".to_owned(), "--config".to_owned(), path.to_string_lossy().into_owned(), "--set=mongo.db=bikes".to_owned()];
+        let config = Config::load(&args);
+        fs::remove_file(&path).unwrap();
+        let config = config.unwrap();
+        assert_eq!(config.http.requests_per_sec, 2.5);
+        assert_eq!(config.mongo.db, "bikes");
+        let err = Config::load(&["moto_spec".to_owned(), "--config".to_owned(), "/nonexistent/motospec.toml".to_owned()]).unwrap_err();
+        assert!(err.to_string().contains("failed to read /nonexistent/motospec.toml"), "{}", err);
+    }
+
+    #[test]
+    fn test_errors_point_at_key() {
+        let err = load(SAMPLE, &[("NUM_OF_HTTP_CONN", "many")], &[]).unwrap_err();
+        assert!(err.to_string().contains("`http.num_conns`"), "{}", err);
+        let err = load(SAMPLE, &[], &["moto_spec", "--set", "http.timeout_sec=3"]).unwrap_err();
+        assert!(err.to_string().contains("`http`"), "{}", err);
+        let err = load(SAMPLE, &[], &["moto_spec", "--set", "retry.max_attempts=0"]).unwrap_err();
+        assert!(err.to_string().contains("`retry.max_attempts`"), "{}", err);
+        let err = load(SAMPLE, &[], &["moto_spec", "--set", "batch.flush_interval_ms=0"]).unwrap_err();
+        assert!(err.to_string().contains("`batch.flush_interval_ms`"), "{}", err);
+        let err = load("[http]\nnum_conns = 4", &[], &[]).unwrap_err();
+        assert!(err.to_string().contains("`mongo.uri`"), "{}", err);
+    }
+}
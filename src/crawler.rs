@@ -1,283 +1,428 @@
-use crate::db::COMPLETED;
-use crate::http::BASE_URL;
+use crate::budget::Budget;
+use crate::classify::PageClass;
+use crate::config::CrawlConfig;
+use crate::db::{COMPLETED, REJECTED};
+use crate::diagnostic::{Diagnostic, Extracted};
+use crate::image::ImageArchive;
+use crate::metrics::Metrics;
+use crate::progress::Progress;
+use crate::queue::{Job, JobQueue, WorkerQueue};
+use crate::report::Report;
 use crate::result::{Brand, Log, LogLevel, Model, Result, Spec};
+use crate::sanity::Sanity;
+use crate::scope::Scope;
+use crate::shutdown::{Interrupted, Shutdown};
+use crate::site::{Site, Sites, UnknownSite};
+use crate::visited::Visited;
 use async_trait::async_trait;
 use futures::future::join_all;
-use futures::{future::BoxFuture, FutureExt};
-use scraper::{ElementRef, Html, Selector};
+use std::collections::HashSet;
 use std::error::Error;
 use std::sync::Arc;
-use url::Url;
+use std::time::Instant;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, info_span, warn, Instrument, Span};
+
+// A file fetched as is, with the media type the server gave for it.
+pub struct Download {
+    pub bytes: Vec<u8>,
+    pub content_type: Option<String>,
+}
 
 #[async_trait]
 pub trait HttpGetter: Send + Sync {
     async fn get(&self, url: &str) -> Result<String>;
+
+    // Fetches a file such as an image under the same rules as pages.
+    async fn get_bytes(&self, url: &str) -> Result<Download> {
+        Err(format!("cannot download {}, only pages are supported", url).into())
+    }
 }
 
+#[async_trait]
 pub trait Store: Send + Sync {
-    fn insert_spec(&self, spec: &Spec) -> Result<()>;
+    async fn insert_spec(&self, spec: &Spec) -> Result<()>;
+
+    // Writes several specs at once, returning one result per spec in order.
+    async fn insert_specs(&self, specs: &[Spec]) -> Vec<Result<()>> {
+        let mut results = Vec::with_capacity(specs.len());
+        for spec in specs {
+            results.push(self.insert_spec(spec).await);
+        }
+        results
+    }
+
+    // Writes out anything buffered, called once before the process exits.
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
+#[async_trait]
 pub trait Logger: Send + Sync {
-    fn insert_log(&self, log: Log<LogLevel, Box<dyn Error + Send + Sync>>) -> Result<()>;
-    fn get_brand_errors(&self) -> Result<Vec<(Brand, String)>>;
-    fn get_model_errors(&self) -> Result<Vec<(Model, String)>>;
-    fn get_spec_errors(&self) -> Result<Vec<(Spec, String)>>;
-    fn update_state(&self, id: &str, state: &str) -> Result<()>;
-    fn increment_retry_count(&self, id: &str) -> Result<()>;
+    async fn insert_log(&self, log: Log<LogLevel, Box<dyn Error + Send + Sync>>) -> Result<()>;
+
+    // Writes several log entries at once, returning one result per entry in order.
+    async fn insert_logs(&self, logs: Vec<Log<LogLevel, Box<dyn Error + Send + Sync>>>) -> Vec<Result<()>> {
+        let mut results = Vec::with_capacity(logs.len());
+        for log in logs {
+            results.push(self.insert_log(log).await);
+        }
+        results
+    }
+    async fn get_brand_errors(&self) -> Result<Vec<(Brand, String)>>;
+    async fn get_model_errors(&self) -> Result<Vec<(Model, String)>>;
+    async fn get_spec_errors(&self) -> Result<Vec<(Spec, String)>>;
+    async fn update_state(&self, id: &str, state: &str) -> Result<()>;
+    async fn increment_retry_count(&self, id: &str) -> Result<()>;
+
+    // Writes out anything buffered, called once before the process exits.
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
-fn extract_brands(html: &str) -> Vec<Brand> {
-    let root = Html::parse_document(html);
-    let selector = Selector::parse("div[class=\"subMenu\"]>a[href*=\"/bikes/\"]").unwrap();
-    root.select(&selector)
-        .map(|node| {
-            let brand_name = node.text().fold(String::new(), |mut s, v| {
-                s.push_str(v);
-                s
-            });
-            let href = Url::parse(BASE_URL).unwrap().join(node.value().attr(&"href").unwrap()).unwrap();
-            Brand::new(brand_name, href.to_string())
-        })
-        .collect()
+// Everything a crawl worker needs, cheap to clone into spawned tasks.
+#[derive(Clone)]
+pub struct Context {
+    pub getter: Arc<dyn HttpGetter>,
+    pub store: Arc<dyn Store>,
+    pub logger: Arc<dyn Logger>,
+    pub scope: Arc<Scope>,
+    pub budget: Arc<Budget>,
+    pub shutdown: Shutdown,
+    pub visited: Arc<Visited>,
+    pub crawl: CrawlConfig,
+    pub progress: Arc<Progress>,
+    pub metrics: Arc<Metrics>,
+    pub report: Arc<Report>,
+    pub sanity: Arc<Sanity>,
+    pub sites: Arc<Sites>,
+    // Set when the images of model pages are downloaded.
+    pub images: Option<Arc<ImageArchive>>,
 }
 
-fn extract_models(html: &str, brand: &str) -> Vec<Model> {
-    let root = Html::parse_document(html);
-    let selector = Selector::parse("td a[href*=\"/model/\"]").unwrap();
-    root.select(&selector)
-        .map(|node| {
-            let href = Url::parse(BASE_URL).unwrap().join(node.value().attr(&"href").unwrap()).unwrap();
-            let model_name = node
-                .text()
-                .fold(String::new(), |mut s, v| {
-                    s.push_str(v);
-                    s
-                })
-                .replace("\n", " ")
-                .trim()
-                .to_owned();
-            if let Some(tr) = node.ancestors().find(|v| {
-                if let Some(ele) = v.value().as_element() {
-                    return &ele.name.local == "tr";
-                }
-                return false;
-            }) {
-                if let Some(year_td) = tr
-                    .children()
-                    .filter(|v| {
-                        if let Some(ele) = v.value().as_element() {
-                            return &ele.name.local == "td";
-                        }
-                        false
-                    })
-                    .nth(1)
-                {
-                    if let Some(ele) = ElementRef::wrap(year_td) {
-                        let year = ele
-                            .text()
-                            .fold(String::new(), |mut s, v| {
-                                s.push_str(v);
-                                s
-                            })
-                            .replace("\n", " ")
-                            .trim()
-                            .to_owned();
-
-                        return Model::new(brand.to_owned(), model_name, year, href.to_string());
-                    }
-                }
-            }
-            Model::new(brand.to_owned(), model_name, "unknown".to_string(), href.to_string())
-        })
-        .collect()
+// Checks the shutdown flag and the budget before a fetch. Refused pages are logged, except retries
+// which keep their existing log entry for a later run.
+async fn admit(ctx: &Context, brand: &str, level: LogLevel, retry: bool) -> bool {
+    if ctx.shutdown.is_triggered() {
+        debug!("not started, shutting down");
+        if !retry {
+            check_logged(ctx, ctx.logger.insert_log(Log::Interrupt(level)).await);
+        }
+        return false;
+    }
+    if let Err(e) = ctx.budget.acquire_page(brand) {
+        info!(reason = %e, "skipped");
+        if !retry {
+            check_logged(ctx, ctx.logger.insert_log(Log::Skip(level, e.to_string())).await);
+        }
+        return false;
+    }
+    true
 }
 
-fn extract_next_page(html: &str, brand: &str, prev_url: &str) -> Option<Brand> {
-    let root = Html::parse_document(&html);
-    let next_selector = Selector::parse("a").unwrap();
-    if let Some(next) = root
-        .select(&next_selector)
-        .filter(|ele| {
-            ele.text()
-                .fold(String::new(), |mut s, v| {
-                    s.push_str(v);
-                    s
-                })
-                .trim()
-                .to_owned()
-                == "Next"
-        })
-        .nth(0)
-    {
-        if let Some(href) = next.value().attr("href") {
-            let href = Url::parse(prev_url).unwrap().join(href).unwrap();
-            return Some(Brand::new(brand.to_owned(), href.to_string()));
+// Records the outcome of a page. Retried pages update their existing log entry instead of adding one,
+// pages refused because of a shutdown are recorded as interrupted so the next run resumes them.
+async fn finish(ctx: &Context, level: LogLevel, log_id: Option<&str>, res: Result<()>) {
+    match &res {
+        Ok(_) => debug!("done"),
+        Err(e) if e.is::<Interrupted>() => info!("interrupted"),
+        Err(e) => warn!(error = %e, "failed"),
+    }
+    if let Err(e) = &res {
+        ctx.progress.error(e.as_ref());
+        if !e.is::<Interrupted>() {
+            ctx.report.page_failed(e.as_ref());
         }
     }
-    None
+    let logged = match (log_id, res) {
+        (Some(id), Ok(_)) => ctx.logger.update_state(id, COMPLETED).await,
+        (Some(_), Err(e)) if e.is::<Interrupted>() => Ok(()),
+        (Some(id), Err(_)) => ctx.logger.increment_retry_count(id).await,
+        (None, Ok(_)) => ctx.logger.insert_log(Log::Log(level)).await,
+        (None, Err(e)) if e.is::<Interrupted>() => ctx.logger.insert_log(Log::Interrupt(level)).await,
+        (None, Err(e)) => ctx.logger.insert_log(Log::Err(level, e)).await,
+    };
+    check_logged(ctx, logged);
 }
 
-fn extract_spec(html: &str, brand: &str, model: &str, year: &str) -> Spec {
-    let root = Html::parse_document(html);
-    let selector = Selector::parse("tr").unwrap();
-    root.select(&selector)
-        .filter(|ele| {
-            ele.children()
-                .filter(|c| {
-                    if let Some(e) = c.value().as_element() {
-                        return &e.name.local == "td";
-                    }
-                    false
-                })
-                .count()
-                == 2
-                && !ele.descendants().any(|c| {
-                    if let Some(e) = c.value().as_element() {
-                        return &e.name.local == "a";
-                    }
-                    false
-                })
-        })
-        .fold(Spec::new(brand.to_owned(), model.to_owned(), year.to_owned()), |mut s, v| {
-            let mut title = ElementRef::wrap(
-                v.children()
-                    .filter(|c| {
-                        if let Some(e) = c.value().as_element() {
-                            return &e.name.local == "td";
-                        }
-                        false
-                    })
-                    .nth(0)
-                    .unwrap(),
-            )
-            .unwrap()
-            .text()
-            .fold(String::new(), |mut ss, vv| {
-                ss.push_str(vv);
-                ss
-            });
-            let mut value = ElementRef::wrap(
-                v.children()
-                    .filter(|c| {
-                        if let Some(e) = c.value().as_element() {
-                            return &e.name.local == "td";
-                        }
-                        false
-                    })
-                    .nth(1)
-                    .unwrap(),
-            )
-            .unwrap()
-            .text()
-            .fold(String::new(), |mut ss, vv| {
-                ss.push_str(vv);
-                ss
-            });
-            title = title.trim().to_owned().replace(".", "");
-            value = value.trim().to_owned();
-            if title != "" && value != "" {
-                s.add_spec(title, value);
-            }
-            s
-        })
+// Reports a log entry which could not be written. The crawl carries on, a worker which stopped here
+// would lose its in-flight entry and the queue slot of its job.
+fn check_logged(ctx: &Context, res: Result<()>) {
+    if let Err(e) = res {
+        error!(error = %e, "failed to write log entry");
+        ctx.progress.error(e.as_ref());
+    }
 }
 
-pub async fn scrape_brands(getter: Arc<dyn HttpGetter>, html: &str, store: Arc<dyn Store>, logger: Arc<dyn Logger>) {
-    let brands = extract_brands(html);
-    let mut handles = Vec::new();
-    for brand in brands {
-        handles.push(tokio::spawn(scrape_models(getter.clone(), brand, store.clone(), logger.clone())));
-    }
-    join_all(handles).await;
-    // for handle in handles {
-    //     if let Err(e) = handle.await {
-    //         println!("{:?}", e);
-    //     }
-    // }
+// Logs what the extractors skipped or could not read on a page, pages read cleanly get no entry.
+async fn diagnose(ctx: &Context, level: LogLevel, diagnostics: Vec<Diagnostic>) {
+    if diagnostics.is_empty() {
+        return;
+    }
+    debug!(diagnostics = diagnostics.len(), "parsed with diagnostics");
+    for diagnostic in &diagnostics {
+        ctx.metrics.parse_diagnostics.inc(diagnostic.get_kind().as_str());
+    }
+    check_logged(ctx, ctx.logger.insert_log(Log::Diagnose(level, diagnostics)).await);
 }
 
-fn scrape_models<'a>(getter: Arc<dyn HttpGetter>, brand: Brand, store: Arc<dyn Store>, logger: Arc<dyn Logger>) -> BoxFuture<'a, ()> {
-    async move {
-        match getter.get(brand.get_url()).await {
-            Ok(html) => {
-                let mut handles = Vec::new();
-                let models = extract_models(&html, brand.get_name());
-                for model in models {
-                    handles.push(tokio::spawn(scrape_specs(getter.clone(), model, store.clone(), logger.clone())));
-                }
-                if let Some(next) = extract_next_page(&html, brand.get_name(), brand.get_url()) {
-                    handles.push(tokio::spawn(scrape_models(getter.clone(), next, store.clone(), logger.clone())));
-                }
-                join_all(handles).await;
-                // for handle in handles {
-                //     if let Err(e) = handle.await {
-                //         println!("{:?}", e);
-                //     }
-                // }
-                logger.insert_log(Log::Log(LogLevel::Brand(brand))).unwrap();
-            }
-            Err(e) => logger.insert_log(Log::Err(LogLevel::Brand(brand), e)).unwrap(),
+// Records a fetched model page which is not a spec page, a retried page leaves the retries for good.
+async fn reject(ctx: &Context, model: Model, log_id: Option<&str>, reason: &str, detail: String) {
+    info!(reason = %detail, "not a spec page");
+    ctx.report.page_not_spec(reason);
+    let logged = match log_id {
+        Some(id) => ctx.logger.update_state(id, REJECTED).await,
+        None => ctx.logger.insert_log(Log::Reject(LogLevel::Model(model), detail)).await,
+    };
+    check_logged(ctx, logged);
+}
+
+// Runs the seed jobs and everything they discover on a pool of `crawl.workers` workers. Seeds are
+// only queued as the queue has room, seeds left when a shutdown is triggered are marked interrupted.
+pub async fn run_jobs(ctx: Context, seeds: Vec<Job>) {
+    let queue = Arc::new(JobQueue::new(ctx.crawl.queue_capacity, ctx.crawl.order));
+    let workers: Vec<_> = (0..ctx.crawl.workers).map(|_| tokio::spawn(work(ctx.clone(), queue.clone()).instrument(Span::current()))).collect();
+    for job in seeds {
+        if ctx.shutdown.is_triggered() {
+            let logged = match job {
+                Job::Brand(brand) | Job::Listing(brand, _) => ctx.logger.insert_log(Log::Interrupt(LogLevel::Brand(brand))).await,
+                Job::Model(model) => ctx.logger.insert_log(Log::Interrupt(LogLevel::Model(model))).await,
+                Job::RetryListing(..) | Job::RetryModel(..) => Ok(()),
+            };
+            check_logged(&ctx, logged);
+            continue;
         }
+        ctx.progress.job_queued(job.get_brand());
+        queue.push_wait(job).await;
     }
-    .boxed()
+    queue.seal();
+    join_all(workers).await;
 }
 
-pub fn retry_scrape_models<'a>(getter: Arc<dyn HttpGetter>, brand: Brand, log_id: String, store: Arc<dyn Store>, logger: Arc<dyn Logger>) -> BoxFuture<'a, ()> {
-    async move {
-        match getter.get(brand.get_url()).await {
-            Ok(html) => {
-                let mut handles = Vec::new();
-                let models = extract_models(&html, brand.get_name());
-                for model in models {
-                    handles.push(tokio::spawn(scrape_specs(getter.clone(), model, store.clone(), logger.clone())));
-                }
-                if let Some(next) = extract_next_page(&html, brand.get_name(), brand.get_url()) {
-                    handles.push(tokio::spawn(scrape_models(getter.clone(), next, store.clone(), logger.clone())));
-                }
-                join_all(handles).await;
-                // for handle in handles {
-                //     if let Err(e) = handle.await {
-                //         println!("{:?}", e);
-                //     }
-                // }
-                logger.update_state(&log_id, COMPLETED).unwrap();
+async fn work(ctx: Context, queue: Arc<JobQueue>) {
+    let queue = WorkerQueue::new(queue);
+    let mut writes = Vec::new();
+    while let Some(job) = queue.pop().await {
+        let brand = job.get_brand().to_owned();
+        let span = job_span(&job);
+        match job {
+            Job::Brand(brand) => scrape_listing(&ctx, &queue, brand, 0, None).instrument(span).await,
+            Job::Listing(page, depth) => scrape_listing(&ctx, &queue, page, depth, None).instrument(span).await,
+            Job::RetryListing(page, log_id) => scrape_listing(&ctx, &queue, page, 0, Some(log_id)).instrument(span).await,
+            Job::Model(model) => scrape_specs(&ctx, model, None, &mut writes).instrument(span).await,
+            Job::RetryModel(model, log_id) => scrape_specs(&ctx, model, Some(log_id), &mut writes).instrument(span).await,
+        }
+        ctx.progress.job_done(&brand);
+        queue.done();
+    }
+    join_all(writes).await;
+}
+
+fn job_span(job: &Job) -> Span {
+    match job {
+        Job::Brand(page) => info_span!("brand", brand = page.get_name(), url = page.get_url()),
+        Job::RetryListing(page, _) => info_span!("brand", brand = page.get_name(), url = page.get_url(), retry = true),
+        Job::Listing(page, depth) => info_span!("listing", brand = page.get_name(), url = page.get_url(), depth),
+        Job::Model(model) => info_span!("model", brand = model.get_brand(), model = model.get_name(), url = model.get_url()),
+        Job::RetryModel(model, _) => info_span!("model", brand = model.get_brand(), model = model.get_name(), url = model.get_url(), retry = true),
+    }
+}
+
+pub async fn scrape_brands(ctx: Context, site: &dyn Site, html: &str) {
+    let Extracted { value: brands, diagnostics } = site.extract_brands(html);
+    let index = Brand::new(site.get_name().to_owned(), site.get_index_url().to_owned());
+    diagnose(&ctx, LogLevel::Brand(index), diagnostics).await;
+    let discovered = brands.iter().map(|b| b.get_url()).collect::<HashSet<_>>().len();
+    ctx.report.brands_discovered(discovered);
+    ctx.sanity.check_brands(discovered, site.get_index_url(), html);
+    let seeds: Vec<Job> = brands.into_iter().filter(|b| ctx.scope.allows_brand(b) && ctx.visited.insert(b.get_url())).map(Job::Brand).collect();
+    for seed in &seeds {
+        ctx.progress.add_brand(seed.get_brand());
+    }
+    info!(brands = seeds.len(), "crawling brands");
+    run_jobs(ctx, seeds).await;
+}
+
+// Retries the brand pages and then the model pages which failed in this or an earlier run.
+pub async fn retry_failed(ctx: Context) -> Result<()> {
+    let brands = ctx.logger.get_brand_errors().await?;
+    let seeds = brands.into_iter().filter(|(b, _)| ctx.scope.allows_brand(b)).map(|(b, id)| Job::RetryListing(b, id)).collect();
+    run_jobs(ctx.clone(), seeds).await;
+    if ctx.shutdown.is_triggered() {
+        return Ok(());
+    }
+    let models = ctx.logger.get_model_errors().await?;
+    let seeds = models.into_iter().filter(|(m, _)| ctx.scope.allows_model(m)).map(|(m, id)| Job::RetryModel(m, id)).collect();
+    run_jobs(ctx.clone(), seeds).await;
+    // A spec which could not be written is not kept, only a new crawl of its model page brings it back.
+    let specs = ctx.logger.get_spec_errors().await?;
+    if !specs.is_empty() {
+        warn!(specs = specs.len(), "specs failed to be stored and are not retried");
+    }
+    Ok(())
+}
+
+// Queues a job discovered by a worker.
+fn enqueue(ctx: &Context, queue: &WorkerQueue, job: Job) {
+    ctx.progress.job_queued(job.get_brand());
+    queue.push(job);
+}
+
+// Queues the in-scope models of a listing page and the next listing page, skipping urls already
+// visited in this run.
+async fn queue_listing(ctx: &Context, queue: &WorkerQueue, site: &dyn Site, html: &str, page: &Brand, depth: u32) {
+    let Extracted { value: models, mut diagnostics } = site.extract_models(html, page);
+    let Extracted {
+        value: next,
+        diagnostics: next_diagnostics,
+    } = site.extract_next_page(html, page);
+    diagnostics.extend(next_diagnostics);
+    diagnose(ctx, LogLevel::Brand(page.clone()), diagnostics).await;
+    debug!(models = models.len(), "parsed listing");
+    for model in models.into_iter().filter(|m| ctx.scope.allows_model(m) && ctx.visited.insert(m.get_url())) {
+        ctx.report.model_found(model.get_brand());
+        enqueue(ctx, queue, Job::Model(model));
+    }
+    if let Some(next) = next {
+        if !ctx.scope.allows_page(next.get_url()) {
+            return;
+        }
+        if !ctx.visited.insert(next.get_url()) {
+            let reason = format!("pagination loop from {}", page.get_url());
+            info!(next = next.get_url(), "pagination loop");
+            check_logged(ctx, ctx.logger.insert_log(Log::Skip(LogLevel::Brand(next), reason)).await);
+            return;
+        }
+        match ctx.budget.check_depth(depth + 1) {
+            Ok(_) => enqueue(ctx, queue, Job::Listing(next, depth + 1)),
+            Err(e) => {
+                info!(next = next.get_url(), reason = %e, "next page skipped");
+                check_logged(ctx, ctx.logger.insert_log(Log::Skip(LogLevel::Brand(next), e.to_string())).await)
             }
-            Err(_) => logger.increment_retry_count(&log_id).unwrap(),
         }
     }
-    .boxed()
 }
 
-async fn scrape_specs(getter: Arc<dyn HttpGetter>, model: Model, store: Arc<dyn Store>, logger: Arc<dyn Logger>) {
-    match getter.get(model.get_url()).await {
+async fn scrape_listing(ctx: &Context, queue: &WorkerQueue, page: Brand, depth: u32, log_id: Option<String>) {
+    let site = match ctx.sites.for_url(page.get_url()) {
+        Some(site) => site,
+        None => return finish(ctx, LogLevel::Brand(page.clone()), log_id.as_deref(), Err(UnknownSite(page.get_url().to_owned()).into())).await,
+    };
+    if !admit(ctx, page.get_name(), LogLevel::Brand(page.clone()), log_id.is_some()).await {
+        return;
+    }
+    let in_flight = log_id.is_none().then(|| ctx.shutdown.track(LogLevel::Brand(page.clone())));
+    let started = Instant::now();
+    let res = ctx.getter.get(page.get_url()).await;
+    ctx.report.page_fetched("listing", started.elapsed());
+    drop(in_flight);
+    let res = match res {
         Ok(html) => {
-            let mut spec = extract_spec(&html, model.get_brand(), model.get_name(), model.get_year());
-            spec.add_spec("Brand".to_owned(), model.get_brand().to_owned());
-            spec.add_spec("Model".to_owned(), model.get_name().to_owned());
-            if let Err(e) = store.insert_spec(&spec) {
-                logger.insert_log(Log::Err(LogLevel::Spec(spec), e)).unwrap();
-            } else {
-                logger.insert_log(Log::Log(LogLevel::Spec(spec))).unwrap();
+            debug!(bytes = html.len(), "fetched");
+            ctx.progress.page_fetched();
+            ctx.budget.add_bytes(html.len());
+            if depth == 0 {
+                ctx.report.brand_scraped(page.get_name());
             }
+            queue_listing(ctx, queue, site, &html, &page, depth).await;
+            Ok(())
         }
-        Err(e) => logger.insert_log(Log::Err(LogLevel::Model(model), e)).unwrap(),
-    }
+        Err(e) => Err(e),
+    };
+    finish(ctx, LogLevel::Brand(page), log_id.as_deref(), res).await;
 }
 
-pub async fn retry_scrape_specs(getter: Arc<dyn HttpGetter>, model: Model, log_id: String, store: Arc<dyn Store>, logger: Arc<dyn Logger>) {
-    match getter.get(model.get_url()).await {
+// Fetches a model page and hands its specs to a write task pushed to `writes`, so the worker moves
+// on while the store, which may hold the specs until its batch is full, writes them.
+async fn scrape_specs(ctx: &Context, model: Model, log_id: Option<String>, writes: &mut Vec<JoinHandle<()>>) {
+    let site = match ctx.sites.for_url(model.get_url()) {
+        Some(site) => site,
+        None => return finish(ctx, LogLevel::Model(model.clone()), log_id.as_deref(), Err(UnknownSite(model.get_url().to_owned()).into())).await,
+    };
+    if !admit(ctx, model.get_brand(), LogLevel::Model(model.clone()), log_id.is_some()).await {
+        return;
+    }
+    let in_flight = log_id.is_none().then(|| ctx.shutdown.track(LogLevel::Model(model.clone())));
+    let started = Instant::now();
+    let res = ctx.getter.get(model.get_url()).await;
+    ctx.report.page_fetched("model", started.elapsed());
+    match res {
         Ok(html) => {
-            let mut spec = extract_spec(&html, model.get_brand(), model.get_name(), model.get_year());
-            spec.add_spec("Brand".to_owned(), model.get_brand().to_owned());
-            spec.add_spec("Model".to_owned(), model.get_name().to_owned());
-            if let Err(_) = store.insert_spec(&spec) {
-                logger.increment_retry_count(&log_id).unwrap();
-            } else {
-                logger.update_state(&log_id, COMPLETED).unwrap();
+            debug!(bytes = html.len(), "fetched");
+            ctx.progress.page_fetched();
+            ctx.budget.add_bytes(html.len());
+            let page = site.extract_specs(&html, &model);
+            let class = site.classify(&page);
+            let Extracted { value: mut specs, diagnostics } = page;
+            diagnose(ctx, LogLevel::Model(model.clone()), diagnostics).await;
+            // Rejected pages count too, a markup change emptying every page must still breach the sanity checks.
+            for spec in &specs {
+                let fields = spec.get_specs().len();
+                debug!(fields, variant = ?spec.get_variant(), "parsed spec");
+                ctx.metrics.specs_parsed.inc();
+                ctx.metrics.spec_fields.observe(fields as f64);
+                if fields == 0 {
+                    ctx.metrics.empty_specs.inc();
+                }
+                ctx.sanity.check_spec(spec, model.get_url(), &html);
+            }
+            if let PageClass::NotSpec { reason, .. } = class {
+                drop(in_flight);
+                return reject(ctx, model, log_id.as_deref(), reason, class.to_string()).await;
             }
+            debug!(variants = specs.len(), "parsed specs");
+            if let (Some(archive), Some(first)) = (&ctx.images, specs.first()) {
+                // The variants of a page share its images, they are downloaded once.
+                let mut images = first.get_images().to_vec();
+                archive.archive(ctx.getter.as_ref(), &mut images).await;
+                for spec in &mut specs {
+                    spec.set_images(images.clone());
+                }
+            }
+            for spec in &mut specs {
+                ctx.report.spec_scraped(spec);
+                // The key is shared by the variants of a model, linking them to each other and to other sources.
+                spec.set_source(site.get_name().to_owned(), ctx.scope.merge_key(model.get_brand(), model.get_name(), model.get_years()));
+            }
+            drop(in_flight);
+            let ctx = ctx.clone();
+            writes.push(tokio::spawn(async move { store_specs(&ctx, model, log_id, specs).await }.instrument(Span::current())));
+        }
+        Err(e) => {
+            drop(in_flight);
+            finish(ctx, LogLevel::Model(model), log_id.as_deref(), Err(e)).await;
         }
-        Err(_) => logger.increment_retry_count(&log_id).unwrap(),
+    }
+}
+
+// Writes the specs of a model page and records the outcome of each once the store has written it.
+async fn store_specs(ctx: &Context, model: Model, log_id: Option<String>, specs: Vec<Spec>) {
+    // The variants of a page are inserted together so they land in the same batch.
+    let results = join_all(specs.iter().map(|spec| ctx.store.insert_spec(spec))).await;
+    let mut stored = Vec::with_capacity(specs.len());
+    for (spec, res) in specs.into_iter().zip(results) {
+        match res {
+            Ok(_) => ctx.progress.spec_stored(),
+            Err(_) => ctx.report.spec_rejected(),
+        }
+        stored.push((spec, res));
+    }
+    match log_id {
+        None => {
+            for (spec, res) in stored {
+                finish(ctx, LogLevel::Spec(Box::new(spec)), None, res).await;
+            }
+        }
+        // A retried page keeps its single log entry, failed again if any variant was not stored.
+        Some(id) => match stored.iter().position(|(_, res)| res.is_err()).or_else(|| stored.len().checked_sub(1)) {
+            Some(i) => {
+                let (spec, res) = stored.swap_remove(i);
+                finish(ctx, LogLevel::Spec(Box::new(spec)), Some(&id), res).await;
+            }
+            None => finish(ctx, LogLevel::Model(model), Some(&id), Err("no specs".into())).await,
+        },
     }
 }
 
@@ -285,34 +430,345 @@ pub async fn retry_scrape_specs(getter: Arc<dyn HttpGetter>, model: Model, log_i
 mod test {
     use tokio::runtime::Runtime;
 
-    use super::scrape_brands;
-    use super::{extract_brands, extract_next_page, extract_spec};
+    use super::{scrape_brands, Context};
+    use crate::budget::Budget;
+    use crate::config::{BudgetConfig, CrawlConfig, HttpConfig, ReportConfig, RetryConfig, SanityAction, SanityConfig};
     use crate::db::{MongoLog, MongoStore};
     use crate::http::{self, HttpClient};
-    use std::sync::Arc;
+    use crate::metrics::Metrics;
+    use crate::profile::Extractor;
+    use crate::progress::Progress;
+    use crate::report::Report;
+    use crate::sanity::Sanity;
+    use crate::scope::Scope;
+    use crate::shutdown::Shutdown;
+    use crate::site::{ProfileSite, Sites};
+    use crate::visited::Visited;
+    use crate::{
+        blocking::{Blocking, BlockingLogger, BlockingStore},
+        crawler::{HttpGetter, Store},
+        result::{Brand, Log, LogLevel, Model, Result, Spec},
+    };
+    use async_trait::async_trait;
+    use std::collections::HashMap;
+    use std::error::Error;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::sync::Semaphore;
+    use tokio::time::{sleep, timeout};
+
+    static INDEX: &str = r#"<html><body><div class="subMenu">
+        <a href="/bikes/honda.html">Honda</a><a href="/bikes/ducati.html">Ducati</a><a href="/bikes/honda.html">Honda</a>
+        </div></body></html>"#;
+    static HONDA_1: &str = r#"<html><body><table>
+        <tr><td><a href="/model/honda/cb500.html">CB 500</a></td><td>2013 - 15</td></tr>
+        <tr><td><a href="/model/honda/cbr600.html">CBR 600</a></td><td>2007</td></tr>
+        </table><a href="honda2.html">Next</a></body></html>"#;
+    static HONDA_2: &str = r#"<html><body><table>
+        <tr><td><a href="/model/honda/cb500.html#top">CB 500</a></td><td>2013 - 15</td></tr>
+        <tr><td><a href="/model/honda/nc750.html">NC 750</a></td><td>2014</td></tr>
+        </table><a href="honda.html">Next</a></body></html>"#;
+    static DUCATI: &str = r#"<html><body><table>
+        <tr><td><a href="/model/ducati/monster.html">Monster</a></td><td>1993</td></tr>
+        <tr><td><a href="/model/ducati/history.html">History</a></td><td>1926</td></tr>
+        </table></body></html>"#;
+    static MODEL: &str = r#"<html><body><table>
+        <tr><td>Engine</td><td>Four stroke, parallel twin</td></tr>
+        <tr><td>Max. Power</td><td>35 kW</td></tr>
+        <tr><td>Price</td><td></td></tr>
+        <tr><td>Dry Weight</td><td>180 kg</td></tr>
+        </table></body></html>"#;
+    static EMPTY_MODEL: &str = r#"<html><body><p>This page has moved.</p></body></html>"#;
+    static HISTORY: &str = r#"<html><body><table>
+        <tr><td><a href="/model/ducati/monster.html">Monster</a></td><td>1993</td></tr>
+        <tr><td><a href="/model/ducati/916.html">916</a></td><td>1994</td></tr>
+        <tr><td>Founded</td><td>1926</td></tr>
+        </table></body></html>"#;
+
+    // Serves fixed pages, unknown urls get the model page.
+    struct FakeGetter(HashMap<&'static str, &'static str>, &'static str);
+
+    #[async_trait]
+    impl HttpGetter for FakeGetter {
+        async fn get(&self, url: &str) -> Result<String> {
+            let path = url.trim_start_matches(http::BASE_URL.trim_end_matches("/index.htm"));
+            if path == "/model/ducati/history.html" {
+                return Ok(HISTORY.to_owned());
+            }
+            if path.starts_with("/model/") {
+                return Ok(self.1.to_owned());
+            }
+            self.0.get(path).map(|s| s.to_string()).ok_or_else(|| format!("no page {}", path).into())
+        }
+    }
+
+    #[derive(Default)]
+    pub(crate) struct MemoryStore(pub(crate) Arc<Mutex<Vec<Spec>>>);
+
+    impl BlockingStore for MemoryStore {
+        fn insert_spec(&self, spec: &Spec) -> Result<()> {
+            self.0.lock().unwrap().push(spec.clone());
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    pub(crate) struct MemoryLog(pub(crate) Arc<Mutex<Vec<String>>>);
+
+    impl BlockingLogger for MemoryLog {
+        fn insert_log(&self, log: Log<LogLevel, Box<dyn Error + Send + Sync>>) -> Result<()> {
+            let (state, level) = match log {
+                Log::Log(level) => ("completed".to_owned(), level),
+                Log::Err(level, e) => (format!("failed: {}", e), level),
+                Log::Skip(level, reason) => (format!("skipped: {}", reason), level),
+                Log::Interrupt(level) => ("interrupted".to_owned(), level),
+                Log::Reject(level, reason) => (format!("rejected: {}", reason), level),
+                Log::Diagnose(level, diagnostics) => (format!("diagnosed: {}", diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(", ")), level),
+            };
+            let name = match level {
+                LogLevel::Brand(b) => b.get_url().to_owned(),
+                LogLevel::Model(m) => m.get_url().to_owned(),
+                LogLevel::Spec(s) => format!("{} {}", s.get_brand(), s.get_model()),
+            };
+            self.0.lock().unwrap().push(format!("{} {}", name, state));
+            Ok(())
+        }
+        fn get_brand_errors(&self) -> Result<Vec<(Brand, String)>> {
+            Ok(Vec::new())
+        }
+        fn get_model_errors(&self) -> Result<Vec<(Model, String)>> {
+            Ok(Vec::new())
+        }
+        fn get_spec_errors(&self) -> Result<Vec<(Spec, String)>> {
+            Ok(Vec::new())
+        }
+        fn update_state(&self, _: &str, _: &str) -> Result<()> {
+            Ok(())
+        }
+        fn increment_retry_count(&self, _: &str) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    // Fails every write, like a log database which went away.
+    struct DownLog;
+
+    impl BlockingLogger for DownLog {
+        fn insert_log(&self, _: Log<LogLevel, Box<dyn Error + Send + Sync>>) -> Result<()> {
+            Err("connection reset".into())
+        }
+        fn get_brand_errors(&self) -> Result<Vec<(Brand, String)>> {
+            Ok(Vec::new())
+        }
+        fn get_model_errors(&self) -> Result<Vec<(Model, String)>> {
+            Ok(Vec::new())
+        }
+        fn get_spec_errors(&self) -> Result<Vec<(Spec, String)>> {
+            Ok(Vec::new())
+        }
+        fn update_state(&self, _: &str, _: &str) -> Result<()> {
+            Err("connection reset".into())
+        }
+        fn increment_retry_count(&self, _: &str) -> Result<()> {
+            Err("connection reset".into())
+        }
+    }
+
+    // Holds every write until released, like a batch which is not full yet.
+    struct GatedStore(Arc<Semaphore>);
+
+    #[async_trait]
+    impl Store for GatedStore {
+        async fn insert_spec(&self, _: &Spec) -> Result<()> {
+            self.0.acquire().await?.forget();
+            Ok(())
+        }
+    }
+
+    // Sanity checks which neither stop the crawl nor save pages.
+    fn alert_only() -> Arc<Sanity> {
+        let config = SanityConfig {
+            action: SanityAction::Alert,
+            dump_dir: String::new(),
+            ..SanityConfig::default()
+        };
+        Arc::new(Sanity::new(&config, Shutdown::new()))
+    }
+
+    pub(crate) type Recorded<T> = Arc<Mutex<Vec<T>>>;
+
+    pub(crate) fn fake_context() -> (Context, Recorded<Spec>, Recorded<String>) {
+        let pages = vec![("/bikes/honda.html", HONDA_1), ("/bikes/honda2.html", HONDA_2), ("/bikes/ducati.html", DUCATI)];
+        let store = MemoryStore::default();
+        let logger = MemoryLog::default();
+        let (specs, logs) = (store.0.clone(), logger.0.clone());
+        let ctx = Context {
+            getter: Arc::new(FakeGetter(pages.into_iter().collect(), MODEL)),
+            store: Arc::new(Blocking::new(store)),
+            logger: Arc::new(Blocking::new(logger)),
+            scope: Arc::new(Scope::default()),
+            budget: Arc::new(Budget::new(&BudgetConfig::default())),
+            shutdown: Shutdown::new(),
+            visited: Arc::new(Visited::default()),
+            crawl: CrawlConfig { workers: 2, ..CrawlConfig::default() },
+            progress: Arc::new(Progress::default()),
+            metrics: Arc::new(Metrics::default()),
+            report: Arc::new(Report::default()),
+            sanity: alert_only(),
+            sites: Arc::new(Sites::new(vec![Arc::new(ProfileSite::new("motorcyclespecs", http::BASE_URL, Extractor::default()))])),
+            images: None,
+        };
+        (ctx, specs, logs)
+    }
+
+    #[test]
+    fn test_run_jobs() {
+        let rt = Runtime::new().unwrap();
+        let (ctx, specs, logs) = fake_context();
+        let (progress, report, sanity) = (ctx.progress.clone(), ctx.report.clone(), ctx.sanity.clone());
+        let site = ctx.sites.get_sites()[0].clone();
+        rt.block_on(scrape_brands(ctx, site.as_ref(), INDEX));
+        let mut models: Vec<String> = specs.lock().unwrap().iter().map(|s| s.get_model().to_owned()).collect();
+        models.sort();
+        assert_eq!(models, vec!["CB 500", "CBR 600", "Monster", "NC 750"]);
+        assert!(specs.lock().unwrap().iter().all(|s| serde_json::to_value(s).unwrap()["Source"] == "motorcyclespecs"));
+        let progress = progress.snapshot();
+        assert_eq!((progress.brands_done, progress.brands_total, progress.specs_stored), (2, 2, 4));
+        let report = report.build(&ReportConfig::default(), Vec::new(), None);
+        assert_eq!((report.brands_discovered, report.brands_scraped, report.specs_scraped), (2, 2, 4));
+        assert_eq!(report.models_per_brand.get("Honda"), Some(&3));
+        assert_eq!(sanity.get_violations(), vec!["found 2 brands on https://www.motorcyclespecs.co.za/index.htm, expected at least 20"]);
+        let logs = logs.lock().unwrap();
+        assert!(
+            logs.iter()
+                .any(|l| l.ends_with("/bikes/honda.html skipped: pagination loop from https://www.motorcyclespecs.co.za/bikes/honda2.html")),
+            "{:?}",
+            logs
+        );
+        assert!(logs.iter().any(|l| l.ends_with(r#"/model/ducati/monster.html diagnosed: empty_value at 2 "Price""#)), "{:?}", logs);
+        assert!(
+            logs.iter().any(|l| l.ends_with("/model/ducati/history.html rejected: mostly links (2 of 3 rows hold links)")),
+            "{:?}",
+            logs
+        );
+        assert_eq!(report.not_spec_pages.get("mostly links"), Some(&1));
+    }
+
+    #[test]
+    fn test_log_failures_do_not_stop_workers() {
+        let rt = Runtime::new().unwrap();
+        let (mut ctx, specs, _) = fake_context();
+        ctx.logger = Arc::new(Blocking::new(DownLog));
+        let progress = ctx.progress.clone();
+        let site = ctx.sites.get_sites()[0].clone();
+        rt.block_on(scrape_brands(ctx, site.as_ref(), INDEX));
+        assert_eq!(specs.lock().unwrap().len(), 4);
+        let progress = progress.snapshot();
+        assert_eq!(progress.brands_done, 2);
+        assert!(progress.errors["other"] > 0);
+    }
+
+    #[test]
+    fn test_workers_do_not_wait_for_writes() {
+        let rt = Runtime::new().unwrap();
+        let (mut ctx, _, _) = fake_context();
+        let gate = Arc::new(Semaphore::new(0));
+        ctx.store = Arc::new(GatedStore(gate.clone()));
+        let progress = ctx.progress.clone();
+        let site = ctx.sites.get_sites()[0].clone();
+        let res = rt.block_on(async {
+            let crawl = tokio::spawn(async move { scrape_brands(ctx, site.as_ref(), INDEX).await });
+            // Three listing pages and five model pages are fetched while no spec is written yet.
+            let fetched = timeout(Duration::from_secs(5), async {
+                while progress.snapshot().pages_fetched < 8 {
+                    sleep(Duration::from_millis(5)).await;
+                }
+            })
+            .await;
+            assert_eq!(progress.snapshot().specs_stored, 0);
+            gate.add_permits(100);
+            crawl.await.unwrap();
+            fetched
+        });
+        assert!(res.is_ok(), "workers waited for the writes of their specs");
+        assert_eq!(progress.snapshot().specs_stored, 4);
+    }
+
+    #[test]
+    fn test_empty_pages_breach() {
+        let rt = Runtime::new().unwrap();
+        let (mut ctx, specs, logs) = fake_context();
+        let pages = vec![("/bikes/honda.html", HONDA_1), ("/bikes/honda2.html", HONDA_2), ("/bikes/ducati.html", DUCATI)];
+        ctx.getter = Arc::new(FakeGetter(pages.into_iter().collect(), EMPTY_MODEL));
+        let config = SanityConfig {
+            min_brands: 0,
+            min_sample: 2,
+            dump_dir: String::new(),
+            ..SanityConfig::default()
+        };
+        ctx.sanity = Arc::new(Sanity::new(&config, ctx.shutdown.clone()));
+        let (metrics, sanity) = (ctx.metrics.clone(), ctx.sanity.clone());
+        let site = ctx.sites.get_sites()[0].clone();
+        rt.block_on(scrape_brands(ctx, site.as_ref(), INDEX));
+        assert!(specs.lock().unwrap().is_empty());
+        assert!(
+            logs.lock().unwrap().iter().any(|l| l.ends_with("rejected: too few rows (0 spec rows, at least 3 expected)")),
+            "{:?}",
+            logs
+        );
+        assert!(metrics.empty_specs.get() >= 2);
+        assert_eq!(sanity.get_violations()[0], "100% of model pages had no spec fields");
+        assert!(sanity.check().is_err());
+    }
 
     #[test]
     fn test_scrape() {
         let rt = Runtime::new().unwrap();
         let html = rt.block_on(http::get(http::BASE_URL)).unwrap();
-        let store = Arc::new(MongoStore::new("mongodb://127.0.0.1", "motospec", "spec").unwrap());
-        let logger = Arc::new(MongoLog::new("mongodb://127.0.0.1", "motospec", "log").unwrap());
-        let client = Arc::new(HttpClient::new(32).unwrap());
-        rt.block_on(scrape_brands(client, &html, store, logger));
+        let store = Arc::new(rt.block_on(MongoStore::new("mongodb://127.0.0.1", "motospec", "spec")).unwrap());
+        let logger = Arc::new(rt.block_on(MongoLog::new("mongodb://127.0.0.1", "motospec", "log")).unwrap());
+        let client = Arc::new(
+            HttpClient::new(
+                &HttpConfig {
+                    num_conns: 32,
+                    ..HttpConfig::default()
+                },
+                &RetryConfig::default(),
+            )
+            .unwrap(),
+        );
+        let ctx = Context {
+            getter: client,
+            store,
+            logger,
+            scope: Arc::new(Scope::default()),
+            budget: Arc::new(Budget::new(&BudgetConfig::default())),
+            shutdown: Shutdown::new(),
+            visited: Arc::new(Visited::default()),
+            crawl: CrawlConfig::default(),
+            progress: Arc::new(Progress::default()),
+            metrics: Arc::new(Metrics::default()),
+            report: Arc::new(Report::default()),
+            sanity: alert_only(),
+            sites: Arc::new(Sites::new(vec![Arc::new(ProfileSite::new("motorcyclespecs", http::BASE_URL, Extractor::default()))])),
+            images: None,
+        };
+        let site = ctx.sites.get_sites()[0].clone();
+        rt.block_on(scrape_brands(ctx, site.as_ref(), &html));
     }
 
     #[test]
     fn test_next_page() {
         let rt = Runtime::new().unwrap();
         let html = rt.block_on(http::get("https://www.motorcyclespecs.co.za/bikes/mv_agusta.html")).unwrap();
-        println!("{:?}", extract_next_page(&html, "MV", "https://www.motorcyclespecs.co.za/bikes/mv_agusta.html"));
+        println!("{:?}", Extractor::default().next_page(&html, "MV", "https://www.motorcyclespecs.co.za/bikes/mv_agusta.html").value);
     }
 
     #[test]
     fn test_extract_brands() {
         let rt = tokio::runtime::Runtime::new().unwrap();
         let html = rt.block_on(http::get(http::BASE_URL)).unwrap();
-        for brand in extract_brands(&html) {
+        for brand in Extractor::default().brands(&html, http::BASE_URL).value {
             println!("{:?}", brand);
         }
     }
@@ -321,7 +777,9 @@ mod test {
     fn test_extract_spec() {
         let rt = tokio::runtime::Runtime::new().unwrap();
         let html = rt.block_on(http::get("https://www.motorcyclespecs.co.za/model/Honda/honda_adv150.html")).unwrap();
-        let spec = extract_spec(&html, "honda", "xadv150", "2021");
-        println!("{:?}", spec);
+        let specs = Extractor::default()
+            .specs(&html, "honda", "xadv150", "2021", "https://www.motorcyclespecs.co.za/model/Honda/honda_adv150.html")
+            .value;
+        println!("{:?}", specs);
     }
 }
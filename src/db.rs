@@ -1,36 +1,52 @@
 use crate::crawler::{Logger, Store};
 use crate::result::{Brand, Log, LogLevel, Model, Spec};
-use mongodb::bson::{doc, to_bson, Document};
-use mongodb::sync::{Client, Collection};
+use async_trait::async_trait;
+use futures::StreamExt;
+use mongodb::bson::{doc, oid::ObjectId, to_bson, to_document, Document};
+use mongodb::error::ErrorKind;
+use mongodb::options::InsertManyOptions;
+use mongodb::results::InsertManyResult;
+use mongodb::{Client, Collection};
 use std::error::Error;
-use std::iter::FromIterator;
 
 use crate::result::Result;
 
 pub struct MongoStore(Collection<Document>);
 
 impl MongoStore {
-    pub fn new(uri: &str, database: &str, collection: &str) -> Result<Self> {
-        let client = Client::with_uri_str(uri)?;
+    pub async fn new(uri: &str, database: &str, collection: &str) -> Result<Self> {
+        let client = Client::with_uri_str(uri).await?;
         let db = client.database(database);
         let col: Collection<Document> = db.collection(collection);
         Ok(Self(col))
     }
 }
 
+fn spec_doc(spec: &Spec) -> Document {
+    to_document(spec).unwrap()
+}
+
+fn unordered() -> InsertManyOptions {
+    InsertManyOptions::builder().ordered(false).build()
+}
+
+#[async_trait]
 impl Store for MongoStore {
-    fn insert_spec(&self, spec: &Spec) -> Result<()> {
-        let specs = spec.get_specs();
-        let doc = Document::from_iter(specs.into_iter().map(|(key, val)| (key.to_owned(), to_bson(val).unwrap())));
-        self.0.insert_one(doc, None).map(|_| ()).map_err(|e| e.into())
+    async fn insert_spec(&self, spec: &Spec) -> Result<()> {
+        self.0.insert_one(spec_doc(spec), None).await.map(|_| ()).map_err(|e| e.into())
+    }
+
+    async fn insert_specs(&self, specs: &[Spec]) -> Vec<Result<()>> {
+        let docs: Vec<Document> = specs.iter().map(spec_doc).collect();
+        insert_many_results(specs.len(), self.0.insert_many(docs, unordered()).await)
     }
 }
 
 pub struct MongoLog(Collection<Document>);
 
 impl MongoLog {
-    pub fn new(uri: &str, database: &str, collection: &str) -> Result<Self> {
-        let client = Client::with_uri_str(uri)?;
+    pub async fn new(uri: &str, database: &str, collection: &str) -> Result<Self> {
+        let client = Client::with_uri_str(uri).await?;
         let db = client.database(database);
         let col: Collection<Document> = db.collection(collection);
         Ok(Self(col))
@@ -39,158 +55,209 @@ impl MongoLog {
 
 pub static COMPLETED: &str = "Completed";
 pub static FAILED: &str = "Failed";
+pub static SKIPPED: &str = "Skipped";
+pub static INTERRUPTED: &str = "Interrupted";
+pub static REJECTED: &str = "Rejected";
+pub static DIAGNOSED: &str = "Diagnosed";
 pub static BRAND: &str = "Brand";
 pub static MODEL: &str = "Model";
 pub static SPEC: &str = "Spec";
 
-impl Logger for MongoLog {
-    fn insert_log(&self, log: Log<LogLevel, Box<dyn Error + Send + Sync>>) -> Result<()> {
-        match log {
-            Log::Log(level) => match level {
-                LogLevel::Brand(brand) => {
-                    self.0.insert_one(
-                        doc! {
-                            "level": BRAND,
-                            "state": COMPLETED,
-                            "content": format!("brand: {}, url: {}", brand.get_name(), brand.get_url()),
-                        },
-                        None,
-                    )?;
-                }
-                LogLevel::Model(model) => {
-                    self.0.insert_one(
-                        doc! {
-                            "level": MODEL,
-                            "state": COMPLETED,
-                            "content": format!("brand: {}, model: {}, year: {}, url: {}", model.get_brand(), model.get_name(), model.get_year(), model.get_url()),
-                        },
-                        None,
-                    )?;
-                }
-                LogLevel::Spec(spec) => {
-                    self.0.insert_one(
-                        doc! {
-                            "level": SPEC,
-                            "state": COMPLETED,
-                            "content": format!("brand: {}, model: {}, year: {}", spec.get_brand(), spec.get_model(), spec.get_year()),
-                        },
-                        None,
-                    )?;
-                }
+// The fields needed to rebuild a brand, model or spec when it is retried.
+fn level_doc(level: &LogLevel, state: &str) -> Document {
+    match level {
+        LogLevel::Brand(brand) => doc! {
+            "level": BRAND,
+            "state": state,
+            "brand": brand.get_name(),
+            "url": brand.get_url(),
+        },
+        LogLevel::Model(model) => doc! {
+            "level": MODEL,
+            "state": state,
+            "brand": model.get_brand(),
+            "model": model.get_name(),
+            "year": model.get_year(),
+            "url": model.get_url(),
+        },
+        LogLevel::Spec(spec) => doc! {
+            "level": SPEC,
+            "state": state,
+            "brand": spec.get_brand(),
+            "model": spec.get_model(),
+            "year": spec.get_year(),
+            "variant": to_bson(&spec.get_variant()).unwrap(),
+        },
+    }
+}
+
+fn log_doc(log: Log<LogLevel, Box<dyn Error + Send + Sync>>) -> Document {
+    match log {
+        Log::Log(level) => match level {
+            LogLevel::Brand(brand) => doc! {
+                "level": BRAND,
+                "state": COMPLETED,
+                "content": format!("brand: {}, url: {}", brand.get_name(), brand.get_url()),
             },
-            Log::Err(level, err) => match level {
-                LogLevel::Brand(brand) => {
-                    self.0.insert_one(
-                        doc! {
-                            "level": BRAND,
-                            "state": FAILED,
-                            "brand": brand.get_name(),
-                            "url": brand.get_url(),
-                            "error": err.to_string(),
-                            "retry_count": 0,
-                        },
-                        None,
-                    )?;
-                }
-                LogLevel::Model(model) => {
-                    self.0.insert_one(
-                        doc! {
-                            "level": MODEL,
-                            "state": FAILED,
-                            "brand": model.get_brand(),
-                            "model": model.get_name(),
-                            "year": model.get_year(),
-                            "url": model.get_url(),
-                            "error": err.to_string(),
-                            "retry_count": 0,
-                        },
-                        None,
-                    )?;
-                }
-                LogLevel::Spec(spec) => {
-                    self.0.insert_one(
-                        doc! {
-                            "level": SPEC,
-                            "state": FAILED,
-                            "brand": spec.get_brand(),
-                            "model": spec.get_model(),
-                            "year": spec.get_year(),
-                            "error": err.to_string(),
-                            "retry_count": 0,
-                        },
-                        None,
-                    )?;
-                }
+            LogLevel::Model(model) => doc! {
+                "level": MODEL,
+                "state": COMPLETED,
+                "content": format!("brand: {}, model: {}, year: {}, url: {}", model.get_brand(), model.get_name(), model.get_year(), model.get_url()),
+            },
+            LogLevel::Spec(spec) => doc! {
+                "level": SPEC,
+                "state": COMPLETED,
+                "content": match spec.get_variant() {
+                    Some(variant) => format!("brand: {}, model: {}, year: {}, variant: {}", spec.get_brand(), spec.get_model(), spec.get_year(), variant),
+                    None => format!("brand: {}, model: {}, year: {}", spec.get_brand(), spec.get_model(), spec.get_year()),
+                },
             },
+        },
+        Log::Err(level, err) => {
+            let mut doc = level_doc(&level, FAILED);
+            doc.insert("error", err.to_string());
+            doc.insert("retry_count", 0);
+            doc
+        }
+        Log::Skip(level, reason) => {
+            let mut doc = level_doc(&level, SKIPPED);
+            doc.insert("reason", reason);
+            doc
+        }
+        Log::Interrupt(level) => {
+            let mut doc = level_doc(&level, INTERRUPTED);
+            doc.insert("retry_count", 0);
+            doc
+        }
+        Log::Reject(level, reason) => {
+            let mut doc = level_doc(&level, REJECTED);
+            doc.insert("reason", reason);
+            doc
         }
+        // Found with {"state": "Diagnosed", "diagnostics.kind": "empty_value"}, never retried.
+        Log::Diagnose(level, diagnostics) => {
+            let mut doc = level_doc(&level, DIAGNOSED);
+            doc.insert("diagnostics", to_bson(&diagnostics).unwrap());
+            doc
+        }
+    }
+}
+
+// Splits the outcome of an unordered insert_many into one result per document.
+fn insert_many_results(n: usize, res: mongodb::error::Result<InsertManyResult>) -> Vec<Result<()>> {
+    let e = match res {
+        Ok(_) => return (0..n).map(|_| Ok(())).collect(),
+        Err(e) => e,
+    };
+    match e.kind.as_ref() {
+        ErrorKind::BulkWrite(failure) if failure.write_concern_error.is_none() => {
+            let mut results: Vec<Result<()>> = (0..n).map(|_| Ok(())).collect();
+            for err in failure.write_errors.iter().flatten() {
+                if let Some(res) = results.get_mut(err.index) {
+                    *res = Err(err.message.clone().into());
+                }
+            }
+            results
+        }
+        _ => (0..n).map(|_| Err(e.to_string().into())).collect(),
+    }
+}
+
+#[async_trait]
+impl Logger for MongoLog {
+    async fn insert_log(&self, log: Log<LogLevel, Box<dyn Error + Send + Sync>>) -> Result<()> {
+        self.0.insert_one(log_doc(log), None).await?;
         Ok(())
     }
 
-    fn get_brand_errors(&self) -> Result<Vec<(Brand, String)>> {
-        let docs = self.0.find(doc! {"level": { "$eq": BRAND }, "state": { "$eq": FAILED }, "retry_count": { "$lte": 3}}, None)?;
+    async fn insert_logs(&self, logs: Vec<Log<LogLevel, Box<dyn Error + Send + Sync>>>) -> Vec<Result<()>> {
+        let n = logs.len();
+        let docs: Vec<Document> = logs.into_iter().map(log_doc).collect();
+        insert_many_results(n, self.0.insert_many(docs, unordered()).await)
+    }
+
+    async fn get_brand_errors(&self) -> Result<Vec<(Brand, String)>> {
+        let mut docs = self
+            .0
+            .find(doc! {"level": { "$eq": BRAND }, "state": { "$in": [FAILED, INTERRUPTED] }, "retry_count": { "$lte": 3}}, None)
+            .await?;
         let mut l = Vec::new();
-        for doc in docs {
+        while let Some(doc) = docs.next().await {
             let doc = doc?;
-            let id = doc.get_str("_id")?;
+            let id = doc.get_object_id("_id")?.to_hex();
             let brand = doc.get_str("brand")?;
             let url = doc.get_str("url")?;
-            l.push((Brand::new(brand.to_owned(), url.to_owned()), id.to_owned()));
+            l.push((Brand::new(brand.to_owned(), url.to_owned()), id));
         }
         Ok(l)
     }
 
-    fn get_model_errors(&self) -> Result<Vec<(Model, String)>> {
-        let docs = self.0.find(doc! {"level": { "$eq": MODEL }, "state": { "$eq": FAILED }, "retry_count": { "$lte": 3}}, None)?;
+    async fn get_model_errors(&self) -> Result<Vec<(Model, String)>> {
+        let mut docs = self
+            .0
+            .find(doc! {"level": { "$eq": MODEL }, "state": { "$in": [FAILED, INTERRUPTED] }, "retry_count": { "$lte": 3}}, None)
+            .await?;
         let mut l = Vec::new();
-        for doc in docs {
+        while let Some(doc) = docs.next().await {
             let doc = doc?;
-            let id = doc.get_str("_id")?;
+            let id = doc.get_object_id("_id")?.to_hex();
             let brand = doc.get_str("brand")?;
             let model = doc.get_str("model")?;
             let year = doc.get_str("year")?;
             let url = doc.get_str("url")?;
-            l.push((Model::new(brand.to_owned(), model.to_owned(), year.to_owned(), url.to_owned()), id.to_owned()));
+            l.push((Model::new(brand.to_owned(), model.to_owned(), year.to_owned(), url.to_owned()), id));
         }
         Ok(l)
     }
 
-    fn get_spec_errors(&self) -> Result<Vec<(Spec, String)>> {
-        let docs = self.0.find(doc! {"level": { "$eq": SPEC }, "state": { "$eq": FAILED }, "retry_count": { "$lte": 3}}, None)?;
+    async fn get_spec_errors(&self) -> Result<Vec<(Spec, String)>> {
+        let mut docs = self
+            .0
+            .find(doc! {"level": { "$eq": SPEC }, "state": { "$in": [FAILED, INTERRUPTED] }, "retry_count": { "$lte": 3}}, None)
+            .await?;
         let mut l = Vec::new();
-        for doc in docs {
+        while let Some(doc) = docs.next().await {
             let doc = doc?;
-            let id = doc.get_str("_id")?;
+            let id = doc.get_object_id("_id")?.to_hex();
             let brand = doc.get_str("brand")?;
             let model = doc.get_str("model")?;
             let year = doc.get_str("year")?;
-            l.push((Spec::new(brand.to_owned(), model.to_owned(), year.to_owned()), id.to_owned()));
+            let mut spec = Spec::new(brand.to_owned(), model.to_owned(), year.to_owned());
+            if let Ok(variant) = doc.get_str("variant") {
+                spec.set_variant(variant.to_owned());
+            }
+            l.push((spec, id));
         }
         Ok(l)
     }
 
-    fn update_state(&self, id: &str, state: &str) -> Result<()> {
-        self.0.update_one(
-            doc! {
-                "_id": id,
-            },
-            doc! {
-                "$set": { "state": state },
-            },
-            None,
-        )?;
+    async fn update_state(&self, id: &str, state: &str) -> Result<()> {
+        self.0
+            .update_one(
+                doc! {
+                    "_id": ObjectId::parse_str(id)?,
+                },
+                doc! {
+                    "$set": { "state": state },
+                },
+                None,
+            )
+            .await?;
         Ok(())
     }
 
-    fn increment_retry_count(&self, id: &str) -> Result<()> {
-        self.0.update_one(
-            doc! {
-                "_id": id,
-            },
-            doc! {
-                "$inc": { "retry_count": 1},
-            },
-            None,
-        )?;
+    async fn increment_retry_count(&self, id: &str) -> Result<()> {
+        self.0
+            .update_one(
+                doc! {
+                    "_id": ObjectId::parse_str(id)?,
+                },
+                doc! {
+                    "$inc": { "retry_count": 1},
+                },
+                None,
+            )
+            .await?;
         Ok(())
     }
 }
@@ -201,20 +268,40 @@ mod test {
     fn db_test() {
         use crate::crawler::Store;
         use crate::db::MongoStore;
-        use crate::result::Spec;
+        use crate::result::{Spec, SpecRow};
 
-        let coll = MongoStore::new("<enter your mongo uri>", "<enter your mongo database>", "<enter your mongo collection>").unwrap();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let coll = rt
+            .block_on(MongoStore::new("<enter your mongo uri>", "<enter your mongo database>", "<enter your mongo collection>"))
+            .unwrap();
         let mut spec = Spec::new("test".to_owned(), "test".to_owned(), "test".to_owned());
-        spec.add_spec("a".to_owned(), "a".to_owned());
-        coll.insert_spec(&spec).unwrap();
+        spec.add_row(SpecRow::new(0, None, "a".to_owned(), "a".to_owned()));
+        rt.block_on(coll.insert_spec(&spec)).unwrap();
+    }
+
+    #[test]
+    fn test_spec_doc() {
+        use super::spec_doc;
+        use crate::result::{Spec, SpecRow};
+
+        let mut spec = Spec::new("Honda".to_owned(), "CB 500".to_owned(), "2013".to_owned());
+        spec.add_row(SpecRow::new(0, None, "Bore x Stroke 2.5 in".to_owned(), "67 x 66.8 mm".to_owned()));
+        let doc = spec_doc(&spec);
+        assert!(doc.keys().all(|k| !k.contains('.')), "{:?}", doc);
+        assert_eq!(doc.get_str("Bore x Stroke 2,5 in").unwrap(), "67 x 66.8 mm");
+        let row = doc.get_array("Rows").unwrap()[0].as_document().unwrap();
+        assert_eq!(row.get_str("key").unwrap(), "Bore x Stroke 2.5 in");
     }
 
     #[test]
     fn test_logger() {
         use super::MongoLog;
         use crate::crawler::Logger;
-        let logger = MongoLog::new("<enter your mongo uri>", "<enter your mongo database>", "<enter your mongo collection>").unwrap();
-        let models = logger.get_model_errors().unwrap();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let logger = rt
+            .block_on(MongoLog::new("<enter your mongo uri>", "<enter your mongo database>", "<enter your mongo collection>"))
+            .unwrap();
+        let models = rt.block_on(logger.get_model_errors()).unwrap();
         for model in models {
             println!("{:?}", model.0);
         }
@@ -0,0 +1,91 @@
+use serde::Serialize;
+use std::fmt;
+
+// What an extractor skipped or could not read on a page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticKind {
+    // A link without the attribute holding its url.
+    MissingHref,
+    // A link whose url could not be resolved against the page.
+    BadUrl,
+    EmptyName,
+    // A listing row without the cell holding the year, the model gets `unknown_year`.
+    MissingYear,
+    // A year cell in which no year could be read.
+    UnknownYear,
+    // A spec row holding an element matched by `exclude`, such as a link.
+    ExcludedRow,
+    EmptyKey,
+    EmptyValue,
+    // A spec row whose key names a field set by the crawler, such as "Model", see `RESERVED_KEYS`.
+    ReservedKey,
+    // A row with a number of cells no spec row or header has.
+    CellCount,
+    // A model page without any spec row.
+    NoSpecs,
+}
+
+impl DiagnosticKind {
+    // The name stored in the log and used as metric label.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::MissingHref => "missing_href",
+            Self::BadUrl => "bad_url",
+            Self::EmptyName => "empty_name",
+            Self::MissingYear => "missing_year",
+            Self::UnknownYear => "unknown_year",
+            Self::ExcludedRow => "excluded_row",
+            Self::EmptyKey => "empty_key",
+            Self::EmptyValue => "empty_value",
+            Self::ReservedKey => "reserved_key",
+            Self::CellCount => "cell_count",
+            Self::NoSpecs => "no_specs",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    kind: DiagnosticKind,
+    // The position of the row or link among those matched on the page.
+    position: Option<usize>,
+    // The text of the element, enough to find it on the page.
+    text: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(kind: DiagnosticKind, position: Option<usize>, text: Option<String>) -> Self {
+        Self { kind, position, text }
+    }
+
+    pub fn get_kind(&self) -> DiagnosticKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind.as_str())?;
+        if let Some(position) = self.position {
+            write!(f, " at {}", position)?;
+        }
+        if let Some(text) = &self.text {
+            write!(f, " {:?}", text)?;
+        }
+        Ok(())
+    }
+}
+
+// The result of an extractor with the diagnostics of the page it was read from.
+#[derive(Debug)]
+pub struct Extracted<T> {
+    pub value: T,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl<T> Extracted<T> {
+    pub fn new(value: T, diagnostics: Vec<Diagnostic>) -> Self {
+        Self { value, diagnostics }
+    }
+}
@@ -0,0 +1,287 @@
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashMap;
+
+// Keys of the rows read, matched case-insensitively. The engine row describes most of the engine,
+// cooling and fuel system often have rows of their own.
+static ENGINE_KEYS: &str = r"(?i)^engine( type)?$";
+static COOLING_KEYS: &str = r"(?i)^cooling( system)?$";
+static FUEL_KEYS: &str = r"(?i)^(induction|fuel system|fuel delivery|carburetion|carburet+or|fuel injection)$";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layout {
+    Single,
+    Inline,
+    // V and L engines.
+    V,
+    Boxer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValveTrain {
+    SideValve,
+    Ohv,
+    Sohc,
+    Dohc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Cooling {
+    Air,
+    AirOil,
+    Liquid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FuelSystem {
+    Carburettor,
+    Injection,
+}
+
+// The engine of a spec as read from its description, such as "Four stroke, transverse four cylinder,
+// DOHC, 4 valves per cylinder". Fields not mentioned are None, the comma separated fragments of the
+// engine row none of them was read from are kept in `unparsed`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Engine {
+    stroke: Option<u8>,
+    cylinders: Option<u8>,
+    layout: Option<Layout>,
+    valve_train: Option<ValveTrain>,
+    desmodromic: bool,
+    valves_per_cylinder: Option<u8>,
+    cooling: Option<Cooling>,
+    fuel_system: Option<FuelSystem>,
+    unparsed: Vec<String>,
+}
+
+// The compiled patterns of the engine parser.
+pub struct EngineParser {
+    engine_keys: Regex,
+    cooling_keys: Regex,
+    fuel_keys: Regex,
+    stroke: Regex,
+    cylinders: Regex,
+    named: Regex,
+    single: Regex,
+    v: Regex,
+    boxer: Regex,
+    inline: Regex,
+    valve_train: Regex,
+    desmo: Regex,
+    valves: Regex,
+    cooling: Regex,
+    fuel: Regex,
+}
+
+impl Default for EngineParser {
+    fn default() -> Self {
+        let re = |s: &str| Regex::new(s).unwrap();
+        Self {
+            engine_keys: re(ENGINE_KEYS),
+            cooling_keys: re(COOLING_KEYS),
+            fuel_keys: re(FUEL_KEYS),
+            stroke: re(r"\b(two|2|four|4)[- ]?stroke\b"),
+            cylinders: re(r"\b(\d{1,2}|one|two|three|four|five|six|eight|ten|twelve)[- ]?cyl(inder)?s?\b"),
+            // Counts named by the layout, as in "parallel twin", "V-4" and "inline-four".
+            named: re(r"\b(twin|triple|(?:in[- ]?line|straight|parallel|flat|boxer|transverse|v|l)[- ]?(two|three|four|five|six|2|3|4|5|6|8))\b"),
+            single: re(r"\bsingle\b|\b(one|1)[- ]?cyl"),
+            v: re(r"\b[vl][- ]?(twin|two|three|four|five|six|\d)\b|\b\d{2,3} ?(°|deg|degrees?)? ?v\b|\bvee\b|\bv[- ]?(type|engine|cylinder)"),
+            boxer: re(r"\b(boxer|flat|opposed)\b"),
+            inline: re(r"\b(in[- ]?line|parallel|straight|transverse)\b"),
+            valve_train: re(r"\b(dohc|sohc|ohv|ohc|side[- ]?valves?|pushrods?|(double|twin|single) overhead cam(shaft)?s?)\b"),
+            desmo: re(r"\bdesmo(dromic)?\b"),
+            valves: re(r"\b(\d{1,2}|two|three|four|five)[- ]?valves?(\s*(per|/|a|each)\s*cyl(inder)?\.?)?"),
+            cooling: re(r"\b(liquid|water|air[/ -]*(and )?oil|oil[/ -]*(and )?air|oil|air)[- ]?cooled\b|\b(liquid|water)[- ]cooling\b|\bair[- ]cooling\b"),
+            fuel: re(r"\b(inject\w*|efi|fi|pgm-fi|carb\w*|mikuni|keihin|dell'?orto|amal|bing)\b"),
+        }
+    }
+}
+
+impl EngineParser {
+    // The engine of a spec, None if it has no engine, cooling or fuel system row.
+    pub fn parse(&self, specs: &HashMap<String, Vec<String>>) -> Option<Engine> {
+        // Of several matching rows the shortest key wins, "Engine" over "Engine Type", whatever the order of the map.
+        let row = |keys: &Regex| specs.iter().filter(|(k, _)| keys.is_match(k)).min_by_key(|(k, _)| (k.len(), k.as_str())).and_then(|(_, vs)| vs.first());
+        let (description, cooling, fuel) = (row(&self.engine_keys), row(&self.cooling_keys), row(&self.fuel_keys));
+        if description.is_none() && cooling.is_none() && fuel.is_none() {
+            return None;
+        }
+        let mut engine = Engine::default();
+        for fragment in description.map(|d| d.split([',', ';']).collect()).unwrap_or_else(Vec::new) {
+            if !self.read(&mut engine, &fragment.to_lowercase()) && !fragment.trim().is_empty() {
+                engine.unparsed.push(fragment.trim().to_owned());
+            }
+        }
+        // The rows of their own only fill in what the description left out.
+        if engine.cooling.is_none() {
+            engine.cooling = cooling.and_then(|c| self.cooling(&c.to_lowercase()).or_else(|| self.cooling(&format!("{} cooled", c.to_lowercase()))));
+        }
+        if engine.fuel_system.is_none() {
+            engine.fuel_system = fuel.and_then(|f| self.fuel_system(&f.to_lowercase()));
+        }
+        // "8 valves" of a four cylinder engine are two per cylinder.
+        if let (Some(total), Some(cylinders)) = (engine.valves_per_cylinder, engine.cylinders) {
+            if total > 5 && cylinders > 1 && total % cylinders == 0 {
+                engine.valves_per_cylinder = Some(total / cylinders);
+            }
+        }
+        Some(engine)
+    }
+
+    // Reads a lowercased fragment into `engine`, returning whether anything was read.
+    fn read(&self, engine: &mut Engine, fragment: &str) -> bool {
+        let mut read = false;
+        if let Some(c) = self.stroke.captures(fragment) {
+            engine.stroke = Some(if matches!(&c[1], "two" | "2") { 2 } else { 4 });
+            read = true;
+        }
+        let cylinders = self
+            .cylinders
+            .captures(fragment)
+            .and_then(|c| count(&c[1]))
+            .or_else(|| self.named.captures(fragment).and_then(|c| c.get(2).map_or_else(|| count(&c[1]), |n| count(n.as_str()))))
+            .or_else(|| self.single.is_match(fragment).then_some(1));
+        if let Some(n) = cylinders {
+            engine.cylinders = Some(n);
+            read = true;
+        }
+        let layout = if self.single.is_match(fragment) {
+            Some(Layout::Single)
+        } else if self.v.is_match(fragment) {
+            Some(Layout::V)
+        } else if self.boxer.is_match(fragment) {
+            Some(Layout::Boxer)
+        } else if self.inline.is_match(fragment) {
+            Some(Layout::Inline)
+        } else {
+            None
+        };
+        if layout.is_some() {
+            engine.layout = layout;
+            read = true;
+        }
+        if let Some(c) = self.valve_train.captures(fragment) {
+            let name = &c[1];
+            engine.valve_train = Some(if name == "dohc" || name.starts_with("double") || name.starts_with("twin") {
+                ValveTrain::Dohc
+            } else if name == "sohc" || name == "ohc" || name.starts_with("single") {
+                ValveTrain::Sohc
+            } else if name.starts_with("side") {
+                ValveTrain::SideValve
+            } else {
+                ValveTrain::Ohv
+            });
+            read = true;
+        }
+        if self.desmo.is_match(fragment) {
+            engine.desmodromic = true;
+            read = true;
+        }
+        if let Some(n) = self.valves.captures(fragment).and_then(|c| count(&c[1])) {
+            engine.valves_per_cylinder = Some(n);
+            read = true;
+        }
+        if let Some(cooling) = self.cooling(fragment) {
+            engine.cooling = Some(cooling);
+            read = true;
+        }
+        if let Some(fuel) = self.fuel_system(fragment) {
+            engine.fuel_system = Some(fuel);
+            read = true;
+        }
+        read
+    }
+
+    fn cooling(&self, text: &str) -> Option<Cooling> {
+        let m = self.cooling.find(text)?.as_str();
+        Some(if m.starts_with("liquid") || m.starts_with("water") {
+            Cooling::Liquid
+        } else if m.contains("oil") {
+            Cooling::AirOil
+        } else {
+            Cooling::Air
+        })
+    }
+
+    fn fuel_system(&self, text: &str) -> Option<FuelSystem> {
+        let m = self.fuel.find(text)?.as_str();
+        Some(if m.starts_with("inject") || m.ends_with("fi") {
+            FuelSystem::Injection
+        } else {
+            FuelSystem::Carburettor
+        })
+    }
+}
+
+fn count(word: &str) -> Option<u8> {
+    match word {
+        "one" | "single" => Some(1),
+        "two" | "twin" => Some(2),
+        "three" | "triple" => Some(3),
+        "four" => Some(4),
+        "five" => Some(5),
+        "six" => Some(6),
+        "eight" => Some(8),
+        "ten" => Some(10),
+        "twelve" => Some(12),
+        n => n.parse().ok().filter(|n| *n > 0),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Cooling, Engine, EngineParser, FuelSystem, Layout, ValveTrain};
+    use std::collections::HashMap;
+
+    fn parse(rows: &[(&str, &str)]) -> Option<Engine> {
+        let specs: HashMap<String, Vec<String>> = rows.iter().map(|(k, v)| (k.to_string(), vec![v.to_string()])).collect();
+        EngineParser::default().parse(&specs)
+    }
+
+    #[test]
+    fn test_parse() {
+        let engine = parse(&[("Engine", "Four stroke, transverse four cylinder, DOHC, 4 valves per cylinder")]).unwrap();
+        assert_eq!(
+            engine,
+            Engine {
+                stroke: Some(4),
+                cylinders: Some(4),
+                layout: Some(Layout::Inline),
+                valve_train: Some(ValveTrain::Dohc),
+                valves_per_cylinder: Some(4),
+                ..Engine::default()
+            }
+        );
+        let engine = parse(&[
+            ("Engine", "Liquid cooled, four stroke, 90° L-twin, Desmodromic, 4 valves per cylinder"),
+            ("Induction", "Siemens fuel injection"),
+        ])
+        .unwrap();
+        assert_eq!((engine.cylinders, engine.layout, engine.desmodromic), (Some(2), Some(Layout::V), true));
+        assert_eq!((engine.cooling, engine.fuel_system), (Some(Cooling::Liquid), Some(FuelSystem::Injection)));
+        let engine = parse(&[("Engine", "Two stroke, single cylinder, reed valve, Euro 5"), ("Cooling System", "Air")]).unwrap();
+        assert_eq!(
+            (engine.stroke, engine.cylinders, engine.layout, engine.cooling),
+            (Some(2), Some(1), Some(Layout::Single), Some(Cooling::Air))
+        );
+        assert_eq!(engine.unparsed, vec!["reed valve", "Euro 5"]);
+        let engine = parse(&[("Engine type", "Air/oil cooled boxer twin, OHV, 8 valves"), ("Carburetion", "2x Bing 32 mm")]).unwrap();
+        assert_eq!((engine.cylinders, engine.layout, engine.valve_train), (Some(2), Some(Layout::Boxer), Some(ValveTrain::Ohv)));
+        assert_eq!(
+            (engine.valves_per_cylinder, engine.cooling, engine.fuel_system),
+            (Some(4), Some(Cooling::AirOil), Some(FuelSystem::Carburettor))
+        );
+        let engine = parse(&[("Engine", "V4, 65°, 16 valves")]).unwrap();
+        assert_eq!((engine.cylinders, engine.layout, engine.valves_per_cylinder), (Some(4), Some(Layout::V), Some(4)));
+        assert_eq!(parse(&[("Frame", "Steel")]), None);
+        // Parsed many times as every map iterates in its own order.
+        for _ in 0..20 {
+            let engine = parse(&[("Engine", "Four stroke, parallel twin"), ("Engine Type", "Single cylinder")]).unwrap();
+            assert_eq!((engine.cylinders, engine.layout), (Some(2), Some(Layout::Inline)));
+        }
+    }
+}
@@ -1,15 +1,24 @@
-use crate::{crawler::HttpGetter, result::Result};
+use crate::config::{HttpConfig, RetryConfig};
+use crate::metrics::Metrics;
+use crate::shutdown::{Interrupted, Shutdown};
+use crate::{
+    crawler::{Download, HttpGetter},
+    result::Result,
+};
 use async_trait::async_trait;
 use reqwest::{
-    header::{HeaderName, HeaderValue},
-    Client, Method,
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
+    Client, Method, Response, StatusCode,
 };
+use std::error::Error;
+use std::fmt;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::Semaphore;
+use tokio::sync::{Mutex, Semaphore};
+use tokio::time::{sleep, Instant};
 
-static DEFAULT_HEADERS: &'static [(&'static str, &'static str)] = &[
+pub(crate) static DEFAULT_HEADERS: &[(&str, &str)] = &[
     (
         "Accept",
         "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,image/apng,*/*;q=0.8,application/signed-exchange;v=b3;q=0.9",
@@ -22,7 +31,6 @@ static DEFAULT_HEADERS: &'static [(&'static str, &'static str)] = &[
         "Cookie",
         "_ga=GA1.3.1854899192.1618532505; _gid=GA1.3.1610962403.1618532505; __gads=ID=27e2c4015d0dff8a-22d058a6a9b90026:T=1618532505:RT=1618532505:S=ALNI_MaixyAR9NetpX_DMq6M9VZ-G9Gn0A",
     ),
-    ("Host", "www.motorcyclespecs.co.za"),
     ("Pragma", "no-cache"),
     // ("Referer", "https://www.motorcyclespecs.co.za/index.htm"),
     ("sec-ch-ua", "\"Google Chrome\";v=\"89\", \"Chromium\";v=\"89\", \";Not A Brand\";v=\"99\""),
@@ -38,38 +46,198 @@ static DEFAULT_HEADERS: &'static [(&'static str, &'static str)] = &[
     ),
 ];
 
-pub static BASE_URL: &'static str = "https://www.motorcyclespecs.co.za/index.htm";
+pub static BASE_URL: &str = "https://www.motorcyclespecs.co.za/index.htm";
 
 #[derive(Debug, Clone)]
 pub struct HttpClient {
     client: Client,
     semaphore: Arc<Semaphore>,
+    headers: HeaderMap,
+    interval: Option<Duration>,
+    next_slot: Arc<Mutex<Instant>>,
+    retry: RetryConfig,
+    shutdown: Option<Shutdown>,
+    metrics: Option<Arc<Metrics>>,
 }
 
 impl HttpClient {
-    pub fn new(num_conns: usize) -> Result<Self> {
-        let client = Client::builder().timeout(Duration::from_secs(10)).build()?;
+    pub fn new(config: &HttpConfig, retry: &RetryConfig) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+            .build()?;
+        let mut headers = HeaderMap::new();
+        for (k, v) in config.get_headers() {
+            headers.insert(HeaderName::from_str(&k)?, HeaderValue::from_str(&v)?);
+        }
+        let interval = if config.requests_per_sec > 0.0 {
+            Some(Duration::from_secs_f64(1.0 / config.requests_per_sec))
+        } else {
+            None
+        };
         Ok(Self {
-            client: client,
-            semaphore: Arc::new(Semaphore::new(num_conns)),
+            client,
+            semaphore: Arc::new(Semaphore::new(config.num_conns)),
+            headers,
+            interval,
+            next_slot: Arc::new(Mutex::new(Instant::now())),
+            retry: retry.clone(),
+            shutdown: None,
+            metrics: None,
         })
     }
+
+    // Requests which have not started when the shutdown is triggered fail with `Interrupted`.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    // Waits until the rate limiter hands out the next request slot.
+    async fn throttle(&self) {
+        if let Some(interval) = self.interval {
+            let wait = {
+                let mut next = self.next_slot.lock().await;
+                let now = Instant::now();
+                let slot = if *next > now { *next } else { now };
+                *next = slot + interval;
+                slot - now
+            };
+            if !wait.is_zero() {
+                sleep(wait).await;
+            }
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let ms = self.retry.backoff_ms.saturating_mul(1 << attempt.min(16));
+        Duration::from_millis(ms.min(self.retry.max_backoff_ms))
+    }
+
+    async fn get_once<B: Body>(&self, url: &str) -> Result<(StatusCode, B)> {
+        self.throttle().await;
+        let started = Instant::now();
+        let res = self.send::<B>(url).await;
+        if let Some(metrics) = &self.metrics {
+            metrics.http_request_seconds.observe_duration(started.elapsed());
+            match &res {
+                Ok((status, body)) => {
+                    metrics.http_requests.inc(status.as_str());
+                    metrics.http_response_bytes.add(body.len() as u64);
+                }
+                Err(_) => metrics.http_requests.inc("error"),
+            }
+        }
+        res
+    }
+
+    async fn send<B: Body>(&self, url: &str) -> Result<(StatusCode, B)> {
+        let req = self.client.request(Method::GET, url).headers(self.headers.clone()).build()?;
+        let res = self.client.execute(req).await?;
+        let status = res.status();
+        Ok((status, B::read(res).await?))
+    }
+
+    // Fetches `url` with the concurrency limit, retrying failed requests and retryable statuses. Any
+    // other unsuccessful status fails at once, a missing page is not read as an empty one.
+    async fn fetch<B: Body>(&self, url: &str) -> Result<B> {
+        let waiting = Instant::now();
+        let _sem = self.semaphore.acquire().await?;
+        if let Some(metrics) = &self.metrics {
+            metrics.http_semaphore_wait_seconds.observe_duration(waiting.elapsed());
+        }
+        let mut attempt = 0;
+        loop {
+            if self.shutdown.as_ref().is_some_and(|s| s.is_triggered()) {
+                return Err(Interrupted.into());
+            }
+            attempt += 1;
+            let res = self.get_once::<B>(url).await;
+            let retry = match &res {
+                Ok((status, _)) => is_retryable(*status),
+                Err(_) => true,
+            };
+            if !retry || attempt >= self.retry.max_attempts {
+                return match res {
+                    Ok((status, _)) if !status.is_success() => Err(StatusError { url: url.to_owned(), status }.into()),
+                    Ok((_, body)) => Ok(body),
+                    Err(e) => Err(e),
+                };
+            }
+            sleep(self.backoff(attempt - 1)).await;
+        }
+    }
+}
+
+// How a response is read, pages as text decoded by their charset and files as bytes.
+#[async_trait]
+trait Body: Sized + Send {
+    async fn read(res: Response) -> reqwest::Result<Self>;
+    fn len(&self) -> usize;
+}
+
+#[async_trait]
+impl Body for String {
+    async fn read(res: Response) -> reqwest::Result<Self> {
+        res.text().await
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+}
+
+#[async_trait]
+impl Body for Download {
+    async fn read(res: Response) -> reqwest::Result<Self> {
+        let content_type = res.headers().get(CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(|v| v.to_owned());
+        Ok(Download {
+            bytes: res.bytes().await?.to_vec(),
+            content_type,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+// A response whose status still asked for a retry after the last attempt.
+#[derive(Debug)]
+pub struct StatusError {
+    pub url: String,
+    pub status: StatusCode,
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GET {} returned {}", self.url, self.status)
+    }
+}
+
+impl Error for StatusError {}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
 }
 
 #[async_trait]
 impl HttpGetter for HttpClient {
     async fn get(&self, url: &str) -> Result<String> {
-        let _sem = self.semaphore.acquire().await?;
-        let req = self
-            .client
-            .request(Method::GET, url)
-            .headers(DEFAULT_HEADERS.iter().map(|(k, v)| (HeaderName::from_str(k).unwrap(), HeaderValue::from_str(v).unwrap())).collect())
-            .build()?;
-        let res = self.client.execute(req).await?;
-        res.text().await.map_err(|e| e.into())
+        self.fetch(url).await
+    }
+
+    async fn get_bytes(&self, url: &str) -> Result<Download> {
+        self.fetch(url).await
     }
 }
 
+#[cfg(test)]
 pub async fn get(url: &str) -> Result<String> {
     let client = Client::new();
     let req = client
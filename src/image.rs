@@ -0,0 +1,166 @@
+use crate::config::ImagesConfig;
+use crate::crawler::HttpGetter;
+use crate::result::Result;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::{debug, warn};
+
+// An image of a model page. `file` is set once the image is in the local archive.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImageRef {
+    url: String,
+    alt: Option<String>,
+    caption: Option<String>,
+    file: Option<StoredImage>,
+}
+
+impl ImageRef {
+    pub fn new(url: String, alt: Option<String>, caption: Option<String>) -> Self {
+        Self { url, alt, caption, file: None }
+    }
+}
+
+// An archived image, stored under the SHA-256 of its content so every image is kept once.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StoredImage {
+    sha256: String,
+    path: String,
+    mime: String,
+    width: usize,
+    height: usize,
+    bytes: usize,
+}
+
+// Downloads the images of model pages into `images.dir`, as <dir>/<first two hex digits>/<hash>.<ext>.
+pub struct ImageArchive {
+    config: ImagesConfig,
+    // Images already archived in this run by url, logos and banners appear on many pages.
+    seen: Mutex<HashMap<String, StoredImage>>,
+}
+
+impl ImageArchive {
+    pub fn new(config: &ImagesConfig) -> Self {
+        Self {
+            config: config.clone(),
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Archives the images through `getter`, so downloads follow the politeness rules of pages. An
+    // image failing to download or to decode is kept without its file.
+    pub async fn archive(&self, getter: &dyn HttpGetter, images: &mut [ImageRef]) {
+        for image in images.iter_mut() {
+            match self.store(getter, &image.url).await {
+                Ok(file) => image.file = Some(file),
+                Err(e) => warn!(url = %image.url, error = %e, "failed to archive image"),
+            }
+        }
+    }
+
+    async fn store(&self, getter: &dyn HttpGetter, url: &str) -> Result<StoredImage> {
+        if let Some(file) = self.seen.lock().unwrap().get(url) {
+            return Ok(file.clone());
+        }
+        let download = getter.get_bytes(url).await?;
+        if download.bytes.len() > self.config.max_bytes {
+            return Err(format!("{} bytes, more than images.max_bytes", download.bytes.len()).into());
+        }
+        let file = self.write(&download.bytes, download.content_type.as_deref())?;
+        debug!(url, path = %file.path, "archived image");
+        self.seen.lock().unwrap().insert(url.to_owned(), file.clone());
+        Ok(file)
+    }
+
+    fn write(&self, bytes: &[u8], content_type: Option<&str>) -> Result<StoredImage> {
+        let size = imagesize::blob_size(bytes).map_err(|e| format!("not an image: {:?}", e))?;
+        let (mime, ext) = match imagesize::image_type(bytes) {
+            Ok(imagesize::ImageType::Jpeg) => ("image/jpeg", "jpg"),
+            Ok(imagesize::ImageType::Png) => ("image/png", "png"),
+            Ok(imagesize::ImageType::Gif) => ("image/gif", "gif"),
+            Ok(imagesize::ImageType::Webp) => ("image/webp", "webp"),
+            Ok(imagesize::ImageType::Bmp) => ("image/bmp", "bmp"),
+            // The sniffed type wins over the header, servers often send images as octet-stream.
+            _ => (content_type.unwrap_or("application/octet-stream"), "bin"),
+        };
+        let sha256 = format!("{:x}", Sha256::digest(bytes));
+        let dir = Path::new(&self.config.dir).join(&sha256[..2]);
+        let path = dir.join(format!("{}.{}", sha256, ext));
+        if !path.exists() {
+            fs::create_dir_all(&dir)?;
+            fs::write(&path, bytes)?;
+        }
+        Ok(StoredImage {
+            sha256,
+            path: path.to_string_lossy().into_owned(),
+            mime: mime.split(';').next().unwrap_or(mime).trim().to_owned(),
+            width: size.width,
+            height: size.height,
+            bytes: bytes.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ImageArchive, ImageRef};
+    use crate::config::ImagesConfig;
+    use crate::crawler::{Download, HttpGetter};
+    use crate::result::Result;
+    use async_trait::async_trait;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // A 2x1 GIF.
+    static GIF: &[u8] = b"GIF89a\x02\x00\x01\x00\x80\x00\x00\x00\x00\x00\xff\xff\xff!\xf9\x04\x00\x00\x00\x00\x00,\x00\x00\x00\x00\x02\x00\x01\x00\x00\x02\x02D\x01\x00;";
+
+    struct Files(AtomicUsize);
+
+    #[async_trait]
+    impl HttpGetter for Files {
+        async fn get(&self, url: &str) -> Result<String> {
+            Err(format!("unexpected page {}", url).into())
+        }
+
+        async fn get_bytes(&self, url: &str) -> Result<Download> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let bytes = if url.ends_with(".gif") { GIF.to_vec() } else { b"<html></html>".to_vec() };
+            Ok(Download {
+                bytes,
+                content_type: Some("application/octet-stream".to_owned()),
+            })
+        }
+    }
+
+    #[test]
+    fn test_archive() {
+        let dir = std::env::temp_dir().join(format!("moto_spec_images_{}", std::process::id()));
+        let config = ImagesConfig {
+            download: true,
+            dir: dir.to_str().unwrap().to_owned(),
+            ..ImagesConfig::default()
+        };
+        let archive = ImageArchive::new(&config);
+        let getter = Files(AtomicUsize::new(0));
+        let mut images = vec![
+            ImageRef::new("https://a/1.gif".to_owned(), Some("CB 500".to_owned()), None),
+            ImageRef::new("https://a/missing.jpg".to_owned(), None, None),
+            ImageRef::new("https://a/1.gif".to_owned(), None, None),
+        ];
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(archive.archive(&getter, &mut images));
+        let files: Vec<_> = fs::read_dir(&dir).unwrap().flat_map(|d| fs::read_dir(d.unwrap().path()).unwrap()).map(|f| f.unwrap().path()).collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(getter.0.load(Ordering::SeqCst), 2);
+        assert_eq!(files.len(), 1);
+        let file = images[0].file.as_ref().unwrap();
+        assert_eq!((file.mime.as_str(), file.width, file.height, file.bytes), ("image/gif", 2, 1, GIF.len()));
+        assert_eq!(files[0].to_str().unwrap(), file.path);
+        assert!(file.path.ends_with(&format!("{}/{}.gif", &file.sha256[..2], file.sha256)));
+        assert_eq!(images[2].file, images[0].file);
+        assert!(images[1].file.is_none());
+    }
+}
@@ -0,0 +1,27 @@
+use crate::config::{LogFormat, TracingConfig};
+use crate::result::Result;
+use std::env;
+use std::io;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing_subscriber::EnvFilter;
+
+// Installs the global tracing subscriber writing to stderr. RUST_LOG takes precedence over
+// `tracing.level` so a single run can be made more verbose without editing the config.
+pub fn init(config: &TracingConfig) -> Result<()> {
+    let filter = match env::var("RUST_LOG") {
+        Ok(directives) if !directives.is_empty() => EnvFilter::try_new(directives)?,
+        _ => EnvFilter::try_new(&config.level)?,
+    };
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_writer(io::stderr);
+    match config.format {
+        LogFormat::Pretty => builder.try_init(),
+        LogFormat::Json => builder.json().with_current_span(true).with_span_list(true).try_init(),
+    }
+}
+
+// Identifies the events of one run, unique enough for runs started from the same host.
+pub fn new_run_id() -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    format!("{:x}-{:x}", nanos, process::id())
+}
@@ -6,37 +6,141 @@ extern crate scraper;
 extern crate tokio;
 extern crate url;
 
+mod batch;
+// Adapts synchronous Store and Logger backends, no backend built into the binary needs it yet.
+#[allow(dead_code)]
+mod blocking;
+mod budget;
+mod chassis;
+mod classify;
+mod config;
 mod crawler;
 mod db;
+mod diagnostic;
+mod engine;
 mod http;
+mod image;
+mod logging;
+mod metrics;
+mod profile;
+mod progress;
+mod queue;
+mod report;
 mod result;
+mod sanity;
+mod scope;
+mod shutdown;
+mod site;
+mod text;
+mod visited;
+mod year;
 
-use crawler::{retry_scrape_models, retry_scrape_specs, scrape_brands, Logger};
+use batch::{BatchLogger, BatchStore};
+use budget::Budget;
+use config::Config;
+use crawler::{retry_failed, scrape_brands, Context, Logger, Store};
 use db::{MongoLog, MongoStore};
 use http::HttpClient;
-use result::Result;
+use image::ImageArchive;
+use metrics::{MeteredLogger, MeteredStore, Metrics};
+use progress::{Progress, Reporter};
+use report::Report;
+use result::{Log, Result};
+use sanity::Sanity;
+use scope::Scope;
+use shutdown::Shutdown;
+use site::Sites;
 use std::env;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, info_span, Instrument};
+use visited::Visited;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let mongo_uri = env::var("MONGO_URI")?;
-    let mongo_db = env::var("MONGO_DB")?;
-    let mongo_data_coll = env::var("MONGO_DATA_COLL")?;
-    let mongo_log_coll = env::var("MONGO_LOG_COLL")?;
-    let num_of_http_conn = env::var("NUM_OF_HTTP_CONN")?.parse::<usize>()?;
-    let store = Arc::new(MongoStore::new(&mongo_uri, &mongo_db, &mongo_data_coll)?);
-    let logger = Arc::new(MongoLog::new(&mongo_uri, &mongo_db, &mongo_log_coll)?);
-    let client = Arc::new(HttpClient::new(num_of_http_conn).unwrap());
-    let html = http::get(http::BASE_URL).await?;
-    scrape_brands(client.clone(), &html, store.clone(), logger.clone()).await;
-    let brands = logger.clone().get_brand_errors()?;
-    for (brand, id) in brands {
-        retry_scrape_models(client.clone(), brand, id, store.clone(), logger.clone()).await;
+    let config = Config::load(&env::args().collect::<Vec<_>>())?;
+    logging::init(&config.tracing)?;
+    let run_id = logging::new_run_id();
+    info!(run_id = %run_id, "starting crawl");
+    let metrics = Arc::new(Metrics::default());
+    if config.metrics.enabled {
+        metrics::serve(metrics.clone(), &config.metrics.listen).await?;
     }
-    let models = logger.clone().get_model_errors()?;
-    for (model, id) in models {
-        retry_scrape_specs(client.clone(), model, id, store.clone(), logger.clone()).await;
+    let store: Arc<dyn Store> = Arc::new(MongoStore::new(&config.mongo.uri, &config.mongo.db, &config.mongo.data_coll).await?);
+    let store: Arc<dyn Store> = Arc::new(MeteredStore::new(store, metrics.clone()));
+    let logger: Arc<dyn Logger> = Arc::new(MongoLog::new(&config.mongo.uri, &config.mongo.db, &config.mongo.log_coll).await?);
+    let logger: Arc<dyn Logger> = BatchLogger::new(Arc::new(MeteredLogger::new(logger, metrics.clone())), &config.batch);
+    let store: Arc<dyn Store> = BatchStore::new(store, &config.batch);
+    let shutdown = Shutdown::new();
+    shutdown.listen();
+    let client = Arc::new(HttpClient::new(&config.http, &config.retry)?.with_shutdown(shutdown.clone()).with_metrics(metrics.clone()));
+    let ctx = Context {
+        getter: client.clone(),
+        store: store.clone(),
+        logger: logger.clone(),
+        scope: Arc::new(Scope::new(&config.scope)?),
+        budget: Arc::new(Budget::new(&config.budget)),
+        shutdown: shutdown.clone(),
+        visited: Arc::new(Visited::new(&config.dedup)),
+        crawl: config.crawl.clone(),
+        progress: Arc::new(Progress::default()),
+        metrics,
+        report: Arc::new(Report::default()),
+        sanity: Arc::new(Sanity::new(&config.sanity, shutdown.clone())),
+        sites: Arc::new(Sites::load(&config.scope.base_url, &config.site)?),
+        images: config.images.download.then(|| Arc::new(ImageArchive::new(&config.images))),
+    };
+    let sanity = ctx.sanity.clone();
+    let report = ctx.report.clone();
+    let reporter = Reporter::start(ctx.progress.clone(), &config.progress);
+    let crawl = crawl(ctx).instrument(info_span!("run", run_id = %run_id));
+    tokio::pin!(crawl);
+    let res = tokio::select! {
+        res = &mut crawl => res,
+        _ = shutdown.wait() => match tokio::time::timeout(Duration::from_secs(config.shutdown.grace_secs), &mut crawl).await {
+            Ok(res) => res,
+            Err(_) => Ok(()),
+        },
+    };
+    for level in shutdown.drain_in_flight() {
+        logger.insert_log(Log::Interrupt(level)).await?;
     }
-    Ok(())
+    let started = Instant::now();
+    store.flush().await?;
+    logger.flush().await?;
+    report.phase("flush", started.elapsed());
+    reporter.finish();
+    if config.report.enabled {
+        let previous = report::load_previous(&config.report.json_path);
+        let report = report.build(&config.report, sanity.get_violations(), previous.as_ref());
+        print!("{}", report.to_text());
+        report.write(&config.report)?;
+    }
+    info!(run_id = %run_id, "crawl finished");
+    res?;
+    Ok(sanity.check()?)
+}
+
+async fn crawl(ctx: Context) -> Result<()> {
+    // Sites are crawled one after the other, their failures are retried together at the end.
+    for site in ctx.sites.get_sites() {
+        if ctx.shutdown.is_triggered() || ctx.budget.is_exhausted() {
+            break;
+        }
+        let span = info_span!("site", site = site.get_name());
+        let started = Instant::now();
+        let html = ctx.getter.get(site.get_index_url()).instrument(span.clone()).await?;
+        ctx.report.phase(&format!("index {}", site.get_name()), started.elapsed());
+        let started = Instant::now();
+        scrape_brands(ctx.clone(), site.as_ref(), &html).instrument(span).await;
+        ctx.report.phase(&format!("crawl {}", site.get_name()), started.elapsed());
+    }
+    // Failures are left for the next run once the run is stopping or out of budget.
+    if ctx.shutdown.is_triggered() || ctx.budget.is_exhausted() {
+        return Ok(());
+    }
+    let started = Instant::now();
+    let res = retry_failed(ctx.clone()).await;
+    ctx.report.phase("retries", started.elapsed());
+    res
 }
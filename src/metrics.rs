@@ -0,0 +1,359 @@
+use crate::crawler::{Logger, Store};
+use crate::result::{Brand, Log, LogLevel, Model, Result, Spec};
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tracing::warn;
+
+static SECONDS_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+static FIELDS_BUCKETS: &[f64] = &[0.0, 5.0, 10.0, 20.0, 40.0, 60.0, 80.0, 100.0, 150.0];
+
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// Counters split by the value of a single label.
+#[derive(Debug)]
+pub struct CounterVec {
+    label: &'static str,
+    values: Mutex<BTreeMap<String, u64>>,
+}
+
+impl CounterVec {
+    fn new(label: &'static str) -> Self {
+        Self {
+            label,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn inc(&self, value: &str) {
+        *self.values.lock().unwrap().entry(value.to_owned()).or_insert(0) += 1;
+    }
+}
+
+#[derive(Debug)]
+pub struct Histogram {
+    bounds: &'static [f64],
+    // Per bucket counts, not cumulative, the last one counts the values above every bound.
+    counts: Vec<AtomicU64>,
+    sum: Mutex<f64>,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum: Mutex::new(0.0),
+        }
+    }
+
+    pub fn observe(&self, value: f64) {
+        let bucket = self.bounds.iter().position(|b| value <= *b).unwrap_or(self.bounds.len());
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+        *self.sum.lock().unwrap() += value;
+    }
+
+    pub fn observe_duration(&self, d: Duration) {
+        self.observe(d.as_secs_f64());
+    }
+
+    pub fn get_count(&self) -> u64 {
+        self.counts.iter().map(|c| c.load(Ordering::Relaxed)).sum()
+    }
+}
+
+// Every metric the crawler exposes on `/metrics`.
+#[derive(Debug)]
+pub struct Metrics {
+    pub http_requests: CounterVec,
+    pub http_request_seconds: Histogram,
+    pub http_response_bytes: Counter,
+    pub http_semaphore_wait_seconds: Histogram,
+    pub specs_parsed: Counter,
+    pub spec_fields: Histogram,
+    pub empty_specs: Counter,
+    pub parse_diagnostics: CounterVec,
+    pub store_writes: CounterVec,
+    pub store_write_failures: CounterVec,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            http_requests: CounterVec::new("status"),
+            http_request_seconds: Histogram::new(SECONDS_BUCKETS),
+            http_response_bytes: Counter::default(),
+            http_semaphore_wait_seconds: Histogram::new(SECONDS_BUCKETS),
+            specs_parsed: Counter::default(),
+            spec_fields: Histogram::new(FIELDS_BUCKETS),
+            empty_specs: Counter::default(),
+            parse_diagnostics: CounterVec::new("kind"),
+            store_writes: CounterVec::new("target"),
+            store_write_failures: CounterVec::new("target"),
+        }
+    }
+}
+
+impl Metrics {
+    // Renders every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        counter_vec(
+            &mut out,
+            "motospec_http_requests_total",
+            "HTTP requests by response status, \"error\" when no response was received.",
+            &self.http_requests,
+        );
+        histogram(&mut out, "motospec_http_request_duration_seconds", "Duration of HTTP requests.", &self.http_request_seconds);
+        counter(&mut out, "motospec_http_response_bytes_total", "Bytes of HTTP response bodies.", &self.http_response_bytes);
+        histogram(
+            &mut out,
+            "motospec_http_semaphore_wait_seconds",
+            "Time spent waiting for a free connection slot.",
+            &self.http_semaphore_wait_seconds,
+        );
+        counter(&mut out, "motospec_specs_parsed_total", "Model pages parsed into a spec.", &self.specs_parsed);
+        histogram(&mut out, "motospec_spec_fields", "Fields extracted per spec.", &self.spec_fields);
+        counter(&mut out, "motospec_empty_specs_total", "Model pages without any spec field.", &self.empty_specs);
+        counter_vec(
+            &mut out,
+            "motospec_parse_diagnostics_total",
+            "Elements the extractors skipped or could not read, by kind.",
+            &self.parse_diagnostics,
+        );
+        counter_vec(&mut out, "motospec_store_writes_total", "Items written to the spec store or the log.", &self.store_writes);
+        counter_vec(
+            &mut out,
+            "motospec_store_write_failures_total",
+            "Items rejected by the spec store or the log.",
+            &self.store_write_failures,
+        );
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, counter: &Counter) {
+    header(out, name, help, "counter");
+    let _ = writeln!(out, "{} {}", name, counter.get());
+}
+
+fn counter_vec(out: &mut String, name: &str, help: &str, counters: &CounterVec) {
+    header(out, name, help, "counter");
+    for (value, n) in counters.values.lock().unwrap().iter() {
+        let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, counters.label, escape(value), n);
+    }
+}
+
+fn histogram(out: &mut String, name: &str, help: &str, histogram: &Histogram) {
+    header(out, name, help, "histogram");
+    let mut total = 0;
+    for (bound, count) in histogram.bounds.iter().zip(&histogram.counts) {
+        total += count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, total);
+    }
+    let count = histogram.get_count();
+    let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+    let _ = writeln!(out, "{}_sum {}", name, histogram.sum.lock().unwrap());
+    let _ = writeln!(out, "{}_count {}", name, count);
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// Serves `GET /metrics` on `addr` in the background and returns the bound address.
+pub async fn serve(metrics: Arc<Metrics>, addr: &str) -> Result<SocketAddr> {
+    let listener = TcpListener::bind(addr).await?;
+    let local = listener.local_addr()?;
+    tokio::spawn(async move {
+        loop {
+            let mut socket = match listener.accept().await {
+                Ok((socket, _)) => socket,
+                Err(e) => {
+                    warn!(error = %e, "metrics endpoint failed to accept a connection");
+                    continue;
+                }
+            };
+            let metrics = metrics.clone();
+            tokio::spawn(async move {
+                let mut buf = [0; 1024];
+                let n = socket.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]);
+                let response = if request.starts_with("GET /metrics ") {
+                    let body = metrics.render();
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                } else {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_owned()
+                };
+                let _ = socket.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+    Ok(local)
+}
+
+fn record(metrics: &Metrics, target: &str, results: &[Result<()>]) {
+    for res in results {
+        match res {
+            Ok(_) => metrics.store_writes.inc(target),
+            Err(_) => metrics.store_write_failures.inc(target),
+        }
+    }
+}
+
+// Counts the writes of a store backend.
+pub struct MeteredStore {
+    inner: Arc<dyn Store>,
+    metrics: Arc<Metrics>,
+}
+
+impl MeteredStore {
+    pub fn new(inner: Arc<dyn Store>, metrics: Arc<Metrics>) -> Self {
+        Self { inner, metrics }
+    }
+}
+
+#[async_trait]
+impl Store for MeteredStore {
+    async fn insert_spec(&self, spec: &Spec) -> Result<()> {
+        let res = self.inner.insert_spec(spec).await;
+        record(&self.metrics, "store", std::slice::from_ref(&res));
+        res
+    }
+
+    async fn insert_specs(&self, specs: &[Spec]) -> Vec<Result<()>> {
+        let results = self.inner.insert_specs(specs).await;
+        record(&self.metrics, "store", &results);
+        results
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.inner.flush().await
+    }
+}
+
+// Counts the writes of a log backend.
+pub struct MeteredLogger {
+    inner: Arc<dyn Logger>,
+    metrics: Arc<Metrics>,
+}
+
+impl MeteredLogger {
+    pub fn new(inner: Arc<dyn Logger>, metrics: Arc<Metrics>) -> Self {
+        Self { inner, metrics }
+    }
+}
+
+#[async_trait]
+impl Logger for MeteredLogger {
+    async fn insert_log(&self, log: Log<LogLevel, Box<dyn Error + Send + Sync>>) -> Result<()> {
+        let res = self.inner.insert_log(log).await;
+        record(&self.metrics, "log", std::slice::from_ref(&res));
+        res
+    }
+
+    async fn insert_logs(&self, logs: Vec<Log<LogLevel, Box<dyn Error + Send + Sync>>>) -> Vec<Result<()>> {
+        let results = self.inner.insert_logs(logs).await;
+        record(&self.metrics, "log", &results);
+        results
+    }
+
+    async fn get_brand_errors(&self) -> Result<Vec<(Brand, String)>> {
+        self.inner.get_brand_errors().await
+    }
+
+    async fn get_model_errors(&self) -> Result<Vec<(Model, String)>> {
+        self.inner.get_model_errors().await
+    }
+
+    async fn get_spec_errors(&self) -> Result<Vec<(Spec, String)>> {
+        self.inner.get_spec_errors().await
+    }
+
+    async fn update_state(&self, id: &str, state: &str) -> Result<()> {
+        let res = self.inner.update_state(id, state).await;
+        record(&self.metrics, "log", std::slice::from_ref(&res));
+        res
+    }
+
+    async fn increment_retry_count(&self, id: &str) -> Result<()> {
+        let res = self.inner.increment_retry_count(id).await;
+        record(&self.metrics, "log", std::slice::from_ref(&res));
+        res
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.inner.flush().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{serve, Metrics};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    #[test]
+    fn test_render_and_serve() {
+        let metrics = Arc::new(Metrics::default());
+        metrics.http_requests.inc("200");
+        metrics.http_requests.inc("200");
+        metrics.http_requests.inc("error");
+        metrics.http_request_seconds.observe(0.2);
+        metrics.http_request_seconds.observe(60.0);
+        metrics.spec_fields.observe(12.0);
+        let text = metrics.render();
+        assert!(text.contains("# TYPE motospec_http_requests_total counter\n"));
+        assert!(text.contains("motospec_http_requests_total{status=\"200\"} 2\n"));
+        assert!(text.contains("motospec_http_requests_total{status=\"error\"} 1\n"));
+        assert!(text.contains("motospec_http_request_duration_seconds_bucket{le=\"0.1\"} 0\n"));
+        assert!(text.contains("motospec_http_request_duration_seconds_bucket{le=\"0.25\"} 1\n"));
+        assert!(text.contains("motospec_http_request_duration_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(text.contains("motospec_http_request_duration_seconds_count 2\n"));
+        assert!(text.contains("motospec_spec_fields_bucket{le=\"20\"} 1\n"));
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let response = rt.block_on(async {
+            let addr = serve(metrics.clone(), "127.0.0.1:0").await.unwrap();
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        });
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(&text));
+    }
+}
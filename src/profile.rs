@@ -0,0 +1,789 @@
+use crate::chassis::ChassisParser;
+use crate::classify::{Classifier, PageClass};
+use crate::config::ConfigError;
+use crate::diagnostic::{Diagnostic, DiagnosticKind, Extracted};
+use crate::engine::EngineParser;
+use crate::image::ImageRef;
+use crate::result::{Brand, Model, Parsed, Result, Spec, SpecRow, RESERVED_KEYS};
+use crate::text;
+use regex::Regex;
+use scraper::{ElementRef, Html, Selector};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use url::Url;
+
+// Rules of the site crawled unless `site.profile` names another file.
+static DEFAULT_PROFILE: &str = include_str!("../profiles/motorcyclespecs.toml");
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Replace {
+    pub pattern: String,
+    pub with: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TextRule {
+    pub replace: Vec<Replace>,
+    pub trim: bool,
+    // Whether the text is normalized before the replacements, see `text::normalize`. The lines of
+    // the element, split at `<br>` and block elements, are joined by `join`.
+    pub normalize: bool,
+    pub join: String,
+}
+
+impl Default for TextRule {
+    fn default() -> Self {
+        Self {
+            replace: Vec::new(),
+            trim: true,
+            normalize: true,
+            join: " ".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BrandRules {
+    pub selector: String,
+    pub href_attr: String,
+    #[serde(default)]
+    pub name: TextRule,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelRules {
+    pub selector: String,
+    pub href_attr: String,
+    #[serde(default)]
+    pub name: TextRule,
+    pub row: String,
+    pub cell: String,
+    pub year_index: usize,
+    #[serde(default)]
+    pub year: TextRule,
+    pub unknown_year: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PaginationRules {
+    pub selector: String,
+    // Matched against the trimmed text of the link, any link matching the selector if unset.
+    pub text: Option<String>,
+    pub href_attr: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpecRules {
+    // Elements holding one spec table each, rows belong to the nearest one. The whole page is one
+    // table if unset.
+    pub table: Option<String>,
+    // A further table sharing at least this share of its keys with the first one describes another
+    // variant of the model, otherwise it continues the table before it.
+    #[serde(default = "default_variant_overlap")]
+    pub variant_overlap: f64,
+    // The page title, from the first element matched.
+    pub title: Option<String>,
+    // Blocks of descriptive or review text, shorter ones are navigation or captions.
+    pub text: Option<String>,
+    #[serde(default)]
+    pub min_text_chars: usize,
+    pub row: String,
+    pub cell: String,
+    pub cells: usize,
+    pub exclude: Option<String>,
+    pub key_index: usize,
+    pub value_index: usize,
+    #[serde(default)]
+    pub key: TextRule,
+    #[serde(default)]
+    pub value: TextRule,
+}
+
+fn default_variant_overlap() -> f64 {
+    0.5
+}
+
+// The images of a model page. The alt text comes from the image, the caption from the figcaption
+// of an enclosing figure or else from the title attribute.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImageRules {
+    pub selector: String,
+    pub src_attr: String,
+}
+
+// When a model page counts as a spec page, other pages linked like models are not stored. The
+// defaults let every page through.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClassifyRules {
+    // Spec rows of the richest variant of the page.
+    pub min_rows: usize,
+    // Patterns matched case-insensitively against the keys of the page, at least `min_expected`
+    // of them must match a key.
+    pub expected_keys: Vec<String>,
+    pub min_expected: usize,
+    // The highest share of table rows dropped for holding links, index and gallery pages are mostly links.
+    pub max_link_share: f64,
+}
+
+impl Default for ClassifyRules {
+    fn default() -> Self {
+        Self {
+            min_rows: 0,
+            expected_keys: Vec::new(),
+            min_expected: 0,
+            max_link_share: 1.0,
+        }
+    }
+}
+
+// Declarative description of how pages of a site are turned into brands, models and specs.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SiteProfile {
+    pub brands: BrandRules,
+    pub models: ModelRules,
+    pub pagination: PaginationRules,
+    pub spec: SpecRules,
+    // Images are not collected if unset.
+    pub images: Option<ImageRules>,
+    #[serde(default)]
+    pub classify: ClassifyRules,
+}
+
+impl SiteProfile {
+    // Reads the profile at `path`, the built-in one if the path is empty.
+    pub fn load(path: &str) -> std::result::Result<Self, ConfigError> {
+        if path.is_empty() {
+            return Self::parse(DEFAULT_PROFILE);
+        }
+        let content = fs::read_to_string(path).map_err(|e| ConfigError::new("site.profile", format!("failed to read {}: {}", path, e)))?;
+        Self::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> std::result::Result<Self, ConfigError> {
+        let mut de = toml::Deserializer::new(content);
+        serde_path_to_error::deserialize(&mut de).map_err(|e| ConfigError::new(&format!("profile.{}", e.path()), e.inner().to_string()))
+    }
+}
+
+struct Text {
+    replace: Vec<(Regex, String)>,
+    trim: bool,
+    // The separator of lines if the text is normalized.
+    join: Option<String>,
+}
+
+impl Text {
+    fn compile(rule: &TextRule, key: &str) -> std::result::Result<Self, ConfigError> {
+        let mut replace = Vec::new();
+        for (i, r) in rule.replace.iter().enumerate() {
+            let re = Regex::new(&r.pattern).map_err(|e| ConfigError::new(&format!("profile.{}.replace[{}]", key, i), e.to_string()))?;
+            replace.push((re, r.with.clone()));
+        }
+        Ok(Self {
+            replace,
+            trim: rule.trim,
+            join: rule.normalize.then(|| rule.join.clone()),
+        })
+    }
+
+    fn clean(&self, ele: ElementRef) -> String {
+        let mut text = match &self.join {
+            Some(sep) => text::clean(ele, sep),
+            None => raw_text(ele),
+        };
+        for (re, with) in &self.replace {
+            text = re.replace_all(&text, with.as_str()).into_owned();
+        }
+        if self.trim {
+            text = text.trim().to_owned();
+        }
+        text
+    }
+}
+
+fn selector(s: &str, key: &str) -> std::result::Result<Selector, ConfigError> {
+    Selector::parse(s).map_err(|e| ConfigError::new(&format!("profile.{}", key), format!("invalid selector {:?}: {:?}", s, e)))
+}
+
+// The text of an element with its whitespace collapsed, for headings and prose spread over lines and tags.
+fn flat_text(ele: ElementRef) -> String {
+    text::clean(ele, " ")
+}
+
+// The text of an element as found in the html, kept with spec rows whose text was cleaned up.
+fn raw_text(ele: ElementRef) -> String {
+    ele.text().collect()
+}
+
+// Direct children of `row` matching `cell`.
+fn cells<'a>(row: ElementRef<'a>, cell: &'a Selector) -> impl Iterator<Item = ElementRef<'a>> + 'a {
+    row.children().filter_map(ElementRef::wrap).filter(move |c| cell.matches(c))
+}
+
+fn join(base: &str, href: &str) -> Option<String> {
+    Some(Url::parse(base).ok()?.join(href).ok()?.to_string())
+}
+
+// The url of the `position`th link matched on a page, noting links without a usable one.
+fn href(link: ElementRef, attr: &str, base: &str, position: usize, diagnostics: &mut Vec<Diagnostic>) -> Option<String> {
+    let href = match link.value().attr(attr) {
+        Some(href) => href,
+        None => {
+            diagnostics.push(Diagnostic::new(DiagnosticKind::MissingHref, Some(position), Some(flat_text(link))));
+            return None;
+        }
+    };
+    let url = join(base, href);
+    if url.is_none() {
+        diagnostics.push(Diagnostic::new(DiagnosticKind::BadUrl, Some(position), Some(href.to_owned())));
+    }
+    url
+}
+
+// A `SiteProfile` with its selectors and patterns compiled.
+pub struct Extractor {
+    profile: SiteProfile,
+    brands: Selector,
+    brand_name: Text,
+    models: Selector,
+    model_name: Text,
+    model_row: Selector,
+    model_cell: Selector,
+    model_year: Text,
+    next: Selector,
+    spec_table: Option<Selector>,
+    spec_title: Option<Selector>,
+    spec_text: Option<Selector>,
+    spec_row: Selector,
+    spec_cell: Selector,
+    spec_exclude: Option<Selector>,
+    spec_key: Text,
+    spec_value: Text,
+    images: Option<Selector>,
+    figcaption: Selector,
+    classifier: Classifier,
+    engine: EngineParser,
+    chassis: ChassisParser,
+}
+
+impl Extractor {
+    pub fn new(profile: SiteProfile) -> std::result::Result<Self, ConfigError> {
+        if !(0.0..=1.0).contains(&profile.spec.variant_overlap) {
+            return Err(ConfigError::new("profile.spec.variant_overlap", "must be between 0 and 1"));
+        }
+        Ok(Self {
+            brands: selector(&profile.brands.selector, "brands.selector")?,
+            brand_name: Text::compile(&profile.brands.name, "brands.name")?,
+            models: selector(&profile.models.selector, "models.selector")?,
+            model_name: Text::compile(&profile.models.name, "models.name")?,
+            model_row: selector(&profile.models.row, "models.row")?,
+            model_cell: selector(&profile.models.cell, "models.cell")?,
+            model_year: Text::compile(&profile.models.year, "models.year")?,
+            next: selector(&profile.pagination.selector, "pagination.selector")?,
+            spec_table: profile.spec.table.as_deref().map(|s| selector(s, "spec.table")).transpose()?,
+            spec_title: profile.spec.title.as_deref().map(|s| selector(s, "spec.title")).transpose()?,
+            spec_text: profile.spec.text.as_deref().map(|s| selector(s, "spec.text")).transpose()?,
+            spec_row: selector(&profile.spec.row, "spec.row")?,
+            spec_cell: selector(&profile.spec.cell, "spec.cell")?,
+            spec_exclude: profile.spec.exclude.as_deref().map(|s| selector(s, "spec.exclude")).transpose()?,
+            spec_key: Text::compile(&profile.spec.key, "spec.key")?,
+            spec_value: Text::compile(&profile.spec.value, "spec.value")?,
+            images: profile.images.as_ref().map(|i| selector(&i.selector, "images.selector")).transpose()?,
+            figcaption: Selector::parse("figcaption").unwrap(),
+            classifier: Classifier::new(&profile.classify)?,
+            engine: EngineParser::default(),
+            chassis: ChassisParser::default(),
+            profile,
+        })
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        Ok(Self::new(SiteProfile::load(path)?)?)
+    }
+
+    pub fn brands(&self, html: &str, base_url: &str) -> Extracted<Vec<Brand>> {
+        let root = Html::parse_document(html);
+        let mut diagnostics = Vec::new();
+        let mut brands = Vec::new();
+        for (i, node) in root.select(&self.brands).enumerate() {
+            if let Some(href) = href(node, &self.profile.brands.href_attr, base_url, i, &mut diagnostics) {
+                let name = self.brand_name.clean(node);
+                if name.is_empty() {
+                    diagnostics.push(Diagnostic::new(DiagnosticKind::EmptyName, Some(i), Some(href.clone())));
+                }
+                brands.push(Brand::new(name, href));
+            }
+        }
+        Extracted::new(brands, diagnostics)
+    }
+
+    pub fn models(&self, html: &str, brand: &str, page_url: &str) -> Extracted<Vec<Model>> {
+        let rules = &self.profile.models;
+        let root = Html::parse_document(html);
+        let mut diagnostics = Vec::new();
+        let mut models = Vec::new();
+        for (i, node) in root.select(&self.models).enumerate() {
+            let href = match href(node, &rules.href_attr, page_url, i, &mut diagnostics) {
+                Some(href) => href,
+                None => continue,
+            };
+            let name = self.model_name.clean(node);
+            if name.is_empty() {
+                diagnostics.push(Diagnostic::new(DiagnosticKind::EmptyName, Some(i), Some(href.clone())));
+            }
+            let year = node
+                .ancestors()
+                .filter_map(ElementRef::wrap)
+                .find(|a| self.model_row.matches(a))
+                .and_then(|row| cells(row, &self.model_cell).nth(rules.year_index))
+                .map(|cell| self.model_year.clean(cell));
+            let model = match year {
+                Some(year) => {
+                    let model = Model::new(brand.to_owned(), name, year, href);
+                    if !model.get_years().is_known() {
+                        diagnostics.push(Diagnostic::new(DiagnosticKind::UnknownYear, Some(i), Some(model.get_year().to_owned())));
+                    }
+                    model
+                }
+                None => {
+                    diagnostics.push(Diagnostic::new(DiagnosticKind::MissingYear, Some(i), Some(name.clone())));
+                    Model::new(brand.to_owned(), name, rules.unknown_year.clone(), href)
+                }
+            };
+            models.push(model);
+        }
+        Extracted::new(models, diagnostics)
+    }
+
+    pub fn next_page(&self, html: &str, brand: &str, prev_url: &str) -> Extracted<Option<Brand>> {
+        let rules = &self.profile.pagination;
+        let root = Html::parse_document(html);
+        let mut diagnostics = Vec::new();
+        let next = root
+            .select(&self.next)
+            .enumerate()
+            .find(|(_, ele)| rules.text.as_ref().is_none_or(|t| flat_text(*ele) == *t))
+            .and_then(|(i, next)| href(next, &rules.href_attr, prev_url, i, &mut diagnostics))
+            .map(|href| Brand::new(brand.to_owned(), href));
+        Extracted::new(next, diagnostics)
+    }
+
+    // The specs of a model page, one for each variant found. Tables repeating the keys of the first
+    // one are further variants, as are the value columns of a table with a header row; the specs of a
+    // page with a single variant have no variant name. The title and text of the page go to every spec.
+    pub fn specs(&self, html: &str, brand: &str, model: &str, year: &str, page_url: &str) -> Extracted<Vec<Spec>> {
+        let root = Html::parse_document(html);
+        let mut diagnostics = Vec::new();
+        let mut tables: Vec<(Option<_>, Table)> = Vec::new();
+        for (position, row) in root.select(&self.spec_row).enumerate() {
+            if self.spec_exclude.as_ref().is_some_and(|ex| row.select(ex).next().is_some()) {
+                diagnostics.push(Diagnostic::new(DiagnosticKind::ExcludedRow, Some(position), Some(flat_text(row))));
+                continue;
+            }
+            let id = self
+                .spec_table
+                .as_ref()
+                .and_then(|t| row.ancestors().filter_map(ElementRef::wrap).find(|a| t.matches(a)).map(|a| a.id()));
+            let i = match tables.iter().position(|(t, _)| *t == id) {
+                Some(i) => i,
+                None => {
+                    tables.push((id, Table::default()));
+                    tables.len() - 1
+                }
+            };
+            self.read_row(&mut tables[i].1, position, row, &mut diagnostics);
+        }
+        let title = self.spec_title.as_ref().and_then(|t| root.select(t).map(flat_text).find(|t| !t.is_empty()));
+        let text: Vec<String> = match &self.spec_text {
+            Some(sel) => root
+                .select(sel)
+                .map(flat_text)
+                .filter(|t| !t.is_empty() && t.chars().count() >= self.profile.spec.min_text_chars)
+                .collect(),
+            None => Vec::new(),
+        };
+        let images = self.images(&root, page_url);
+        let new_spec = || {
+            let mut spec = Spec::new(brand.to_owned(), model.to_owned(), year.to_owned());
+            spec.set_page(title.clone(), text.clone(), images.clone());
+            spec
+        };
+        let tables: Vec<Table> = tables.into_iter().map(|(_, t)| t).filter(|t| !t.rows.is_empty()).collect();
+        if tables.is_empty() {
+            diagnostics.push(Diagnostic::new(DiagnosticKind::NoSpecs, None, title.clone()));
+        }
+        let mut groups: Vec<Vec<&Table>> = Vec::new();
+        for table in &tables {
+            let continues = match (tables.first(), groups.last()) {
+                (Some(first), Some(group)) => table.overlap(first) < self.profile.spec.variant_overlap && (table.columns() == 1 || table.columns() == group[0].columns()),
+                _ => false,
+            };
+            match groups.last_mut() {
+                Some(group) if continues => group.push(table),
+                _ => groups.push(vec![table]),
+            }
+        }
+        let mut specs = Vec::new();
+        for group in &groups {
+            for column in 0..group[0].columns() {
+                let mut spec = new_spec();
+                for row in group.iter().flat_map(|t| &t.rows) {
+                    let (value, raw) = if row.values.len() == 1 { &row.values[0] } else { &row.values[column] };
+                    if !value.is_empty() {
+                        spec.add_row(SpecRow::new(row.position, row.section.clone(), row.key.clone(), value.clone()).with_raw(&row.raw_key, raw));
+                    }
+                }
+                let name: Vec<&str> = group[0].title.iter().chain(group[0].header.get(column)).map(|s| s.as_str()).filter(|s| !s.is_empty()).collect();
+                specs.push((spec, name.join(" ")));
+            }
+        }
+        let mut specs: Vec<Spec> = if specs.len() < 2 {
+            vec![specs.pop().map(|(spec, _)| spec).unwrap_or_else(new_spec)]
+        } else {
+            // Tables often start with the same section heading, such names are no use to tell variants apart.
+            let names: Vec<String> = specs.iter().map(|(_, name)| name.clone()).collect();
+            specs
+                .into_iter()
+                .enumerate()
+                .map(|(i, (mut spec, name))| {
+                    let unique = !name.is_empty() && names.iter().filter(|n| **n == name).count() == 1;
+                    spec.set_variant(if unique { name } else { format!("Variant {}", i + 1) });
+                    spec
+                })
+                .collect()
+        };
+        for spec in &mut specs {
+            let parsed = Parsed {
+                engine: self.engine.parse(spec.get_specs()),
+                chassis: self.chassis.parse(spec.get_specs()).map(Box::new),
+            };
+            spec.set_parsed(parsed);
+        }
+        Extracted::new(specs, diagnostics)
+    }
+
+    // Whether the page the specs were extracted from is a spec page.
+    pub fn classify(&self, page: &Extracted<Vec<Spec>>) -> PageClass {
+        self.classifier.classify(page)
+    }
+
+    // The images of the page in order, each url once.
+    fn images(&self, root: &Html, page_url: &str) -> Vec<ImageRef> {
+        let (sel, rules) = match (&self.images, &self.profile.images) {
+            (Some(sel), Some(rules)) => (sel, rules),
+            _ => return Vec::new(),
+        };
+        let mut seen = HashSet::new();
+        let mut images = Vec::new();
+        for img in root.select(sel) {
+            let url = match img.value().attr(&rules.src_attr).filter(|src| !src.starts_with("data:")).and_then(|src| join(page_url, src)) {
+                Some(url) => url,
+                None => continue,
+            };
+            if !seen.insert(url.clone()) {
+                continue;
+            }
+            let attr = |name: &str| img.value().attr(name).map(text::normalize).filter(|v| !v.is_empty());
+            let figcaption = img
+                .ancestors()
+                .filter_map(ElementRef::wrap)
+                .find(|a| a.value().name() == "figure")
+                .and_then(|f| f.select(&self.figcaption).next())
+                .map(flat_text)
+                .filter(|c| !c.is_empty());
+            images.push(ImageRef::new(url, attr("alt"), figcaption.or_else(|| attr("title"))));
+        }
+        images
+    }
+
+    // Adds a row to its table. A single cell is a section heading such as "Engine" for the rows below,
+    // the first one before any spec row also titles the table. A row wider than `cells` with an empty
+    // key cell names the value columns of the rows of the same width. Rows dropped for an empty key
+    // or value or an unexpected number of cells are noted in `diagnostics`.
+    fn read_row(&self, table: &mut Table, position: usize, row: ElementRef, diagnostics: &mut Vec<Diagnostic>) {
+        let rules = &self.profile.spec;
+        let row_cells: Vec<ElementRef> = cells(row, &self.spec_cell).collect();
+        if row_cells.len() == 1 && rules.cells > 1 {
+            let heading = flat_text(row_cells[0]);
+            if heading.is_empty() {
+                return;
+            }
+            if table.title.is_none() && table.rows.is_empty() && table.header.is_empty() {
+                table.title = Some(heading.clone());
+            }
+            table.section = Some(heading);
+            return;
+        }
+        let note = |kind, text| Diagnostic::new(kind, Some(position), Some(text));
+        let (key, raw_key) = match row_cells.get(rules.key_index) {
+            Some(k) if row_cells.len() >= rules.cells => (self.spec_key.clean(*k), raw_text(*k)),
+            _ if row_cells.is_empty() => return,
+            _ => return diagnostics.push(note(DiagnosticKind::CellCount, flat_text(row))),
+        };
+        if RESERVED_KEYS.contains(&key.as_str()) {
+            diagnostics.push(note(DiagnosticKind::ReservedKey, key.clone()));
+        }
+        let value = |v: &ElementRef| (self.spec_value.clean(*v), raw_text(*v));
+        if row_cells.len() == rules.cells {
+            let value = match row_cells.get(rules.value_index) {
+                Some(v) => value(v),
+                None => return diagnostics.push(note(DiagnosticKind::CellCount, flat_text(row))),
+            };
+            match (key.is_empty(), value.0.is_empty()) {
+                (false, false) => table.push(position, key, raw_key, vec![value]),
+                (true, false) => diagnostics.push(note(DiagnosticKind::EmptyKey, value.0)),
+                (false, true) => diagnostics.push(note(DiagnosticKind::EmptyValue, key)),
+                (true, true) => (),
+            }
+            return;
+        }
+        let values: Vec<(String, String)> = row_cells.iter().skip(rules.value_index).map(value).collect();
+        if key.is_empty() && table.header.is_empty() {
+            table.header = values.into_iter().map(|(v, _)| v).collect();
+        } else if key.is_empty() {
+            diagnostics.push(note(DiagnosticKind::EmptyKey, flat_text(row)));
+        } else if values.len() > 1 && values.len() == table.header.len() {
+            table.push(position, key, raw_key, values);
+        } else {
+            diagnostics.push(note(DiagnosticKind::CellCount, key));
+        }
+    }
+}
+
+// A spec row with a value for each column of its table or a single value shared by all columns,
+// each value as cleaned and as found on the page.
+struct TableRow {
+    position: usize,
+    section: Option<String>,
+    key: String,
+    raw_key: String,
+    values: Vec<(String, String)>,
+}
+
+#[derive(Default)]
+struct Table {
+    title: Option<String>,
+    // The heading of the rows read next.
+    section: Option<String>,
+    header: Vec<String>,
+    rows: Vec<TableRow>,
+}
+
+impl Table {
+    fn push(&mut self, position: usize, key: String, raw_key: String, values: Vec<(String, String)>) {
+        self.rows.push(TableRow {
+            position,
+            section: self.section.clone(),
+            key,
+            raw_key,
+            values,
+        });
+    }
+
+    fn columns(&self) -> usize {
+        self.header.len().max(1)
+    }
+
+    // The share of the keys of this table also found in `other`.
+    fn overlap(&self, other: &Table) -> f64 {
+        let keys: HashSet<&str> = self.rows.iter().map(|r| r.key.as_str()).collect();
+        let other: HashSet<&str> = other.rows.iter().map(|r| r.key.as_str()).collect();
+        keys.intersection(&other).count() as f64 / keys.len() as f64
+    }
+}
+
+impl Default for Extractor {
+    fn default() -> Self {
+        Self::new(SiteProfile::parse(DEFAULT_PROFILE).unwrap()).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Extractor, SiteProfile, DEFAULT_PROFILE};
+    use crate::diagnostic::DiagnosticKind;
+
+    static LISTING: &str = r#"<html><body><table>
+        <tr><td><a href="/model/honda/cb500.html">CB
+500</a></td><td> 2013 - 15 </td></tr>
+        <tr><td><a href="/model/honda/x.html">X</a></td></tr>
+        <tr><td><a href="/model/honda/cb1.html">CB 1</a></td><td>n/a</td></tr>
+        </table><a href="honda2.html"> Next </a></body></html>"#;
+    static MODEL: &str = r#"<html><body><table>
+        <tr><td>Max. Power</td><td> 35 kW </td></tr>
+        <tr><td>Dealer</td><td><a href="/">link</a></td></tr>
+        <tr><td>Empty</td><td></td></tr>
+        <tr><td>a</td><td>b</td><td>c</td></tr>
+        <tr><td>Wet Weight</td><td>190 kg</td></tr>
+        <tr><td>Wet Weight</td><td>192 kg (ABS)</td></tr>
+        <tr><td>Tyres:</td><td>Front 120/70&nbsp;ZR17<br>
+            Rear 160/60 ZR17</td></tr>
+        <tr><td>Bore x Stroke 2.5 in.</td><td>67 x 66.8 mm</td></tr>
+        <tr><td>Model</td><td>CB 500 F</td></tr>
+        </table></body></html>"#;
+
+    #[test]
+    fn test_default_profile() {
+        let extractor = Extractor::default();
+        let listing = extractor.models(LISTING, "Honda", "https://a/bikes/honda.html");
+        let models = listing.value;
+        assert_eq!(models.len(), 3);
+        assert_eq!(
+            (models[0].get_name(), models[0].get_year(), models[0].get_url()),
+            ("CB 500", "2013 - 15", "https://a/model/honda/cb500.html")
+        );
+        assert_eq!(models[1].get_year(), "unknown");
+        let kinds: Vec<_> = listing.diagnostics.iter().map(|d| d.get_kind()).collect();
+        assert_eq!(kinds, vec![DiagnosticKind::MissingYear, DiagnosticKind::UnknownYear]);
+        assert_eq!(listing.diagnostics[1].to_string(), r#"unknown_year at 2 "n/a""#);
+        let next = extractor.next_page(LISTING, "Honda", "https://a/bikes/honda.html").value.unwrap();
+        assert_eq!(next.get_url(), "https://a/bikes/honda2.html");
+        let page = extractor.specs(MODEL, "Honda", "CB 500", "2013", "https://a/model/honda/cb500.html");
+        let kinds: Vec<_> = page.diagnostics.iter().map(|d| d.get_kind()).collect();
+        assert_eq!(
+            kinds,
+            vec![DiagnosticKind::ExcludedRow, DiagnosticKind::EmptyValue, DiagnosticKind::CellCount, DiagnosticKind::ReservedKey]
+        );
+        assert_eq!(
+            serde_json::to_value(&page.diagnostics[1]).unwrap(),
+            serde_json::json!({"kind": "empty_value", "position": 2, "text": "Empty"})
+        );
+        let mut specs = page.value;
+        assert_eq!(specs.len(), 1);
+        let spec = specs.remove(0);
+        assert_eq!(spec.get_variant(), None);
+        assert_eq!(spec.get_specs().len(), 5);
+        assert_eq!(spec.get_specs()["Max Power"], vec!["35 kW"]);
+        assert_eq!(spec.get_specs()["Wet Weight"], vec!["190 kg", "192 kg (ABS)"]);
+        assert_eq!(spec.get_specs()["Tyres"], vec!["Front 120/70 ZR17; Rear 160/60 ZR17"]);
+        assert_eq!(spec.get_specs()["Bore x Stroke 2,5 in"], vec!["67 x 66.8 mm"]);
+        let rows = serde_json::json!([
+            {"position": 0, "section": null, "key": "Max Power", "value": "35 kW", "raw_key": "Max. Power", "raw_value": " 35 kW "},
+            {"position": 4, "section": null, "key": "Wet Weight", "value": "190 kg"},
+            {"position": 5, "section": null, "key": "Wet Weight", "value": "192 kg (ABS)"},
+            {"position": 6, "section": null, "key": "Tyres", "value": "Front 120/70 ZR17; Rear 160/60 ZR17", "raw_key": "Tyres:", "raw_value": "Front 120/70\u{a0}ZR17\n            Rear 160/60 ZR17"},
+            {"position": 7, "section": null, "key": "Bore x Stroke 2.5 in", "value": "67 x 66.8 mm", "raw_key": "Bore x Stroke 2.5 in."},
+            {"position": 8, "section": null, "key": "Model", "value": "CB 500 F"},
+        ]);
+        let doc = serde_json::to_value(&spec).unwrap();
+        assert_eq!(doc["Rows"], rows);
+        // The row named like a field set by the crawler is kept in the rows only.
+        assert_eq!(doc["Model"], "CB 500");
+    }
+
+    #[test]
+    fn test_variants() {
+        let extractor = Extractor::default();
+        let html = r#"<table><tr><td>
+            <table><tr><td>Engine</td><td>Twin</td></tr><tr><td>Power</td><td>35 kW</td></tr></table>
+            <table><tr><td>Frame</td><td>Steel</td></tr></table>
+            <table><tr><td colspan="2">ABS</td></tr><tr><td>Engine</td><td>Twin</td></tr><tr><td>Power</td><td>34 kW</td></tr></table>
+            </td></tr></table>"#;
+        let specs = extractor.specs(html, "Honda", "CB 500", "2013", "https://a/model/honda/cb500.html").value;
+        assert_eq!(specs.iter().map(|s| s.get_variant()).collect::<Vec<_>>(), vec![Some("Variant 1"), Some("ABS")]);
+        assert_eq!(specs[0].get_specs()["Frame"], vec!["Steel"]);
+        assert_eq!(specs[1].get_specs()["Power"], vec!["34 kW"]);
+        assert!(!specs[1].get_specs().contains_key("Frame"));
+
+        let html = r#"<table>
+            <tr><td>Engine</td><td>Twin</td></tr>
+            <tr><td></td><td>EU</td><td>US</td></tr>
+            <tr><td>Power</td><td>35 kW</td><td>38 kW</td></tr>
+            <tr><td>Torque</td><td>43 Nm</td><td></td></tr>
+            </table>"#;
+        let specs = extractor.specs(html, "Honda", "CB 500", "2013", "https://a/model/honda/cb500.html").value;
+        assert_eq!(specs.iter().map(|s| s.get_variant()).collect::<Vec<_>>(), vec![Some("EU"), Some("US")]);
+        assert_eq!(specs[1].get_specs()["Engine"], vec!["Twin"]);
+        assert_eq!(specs[1].get_specs()["Power"], vec!["38 kW"]);
+        assert!(!specs[1].get_specs().contains_key("Torque"));
+
+        let html = r#"<html><head><title>
+            Honda CB 500 </title></head><body><p>Short</p><table>
+            <tr><td colspan="2"><b>Engine</b></td></tr><tr><td>Engine</td><td>Twin</td></tr>
+            <tr><td colspan="2">Chassis</td></tr><tr><td>Frame</td><td>Steel</td></tr><tr><td>Front Tyre</td><td>120/70 ZR17</td></tr>
+            </table><p>The CB 500 is a   parallel twin aimed at new riders,
+            sold alongside the <b>CBR 500 R</b> and the CB 500 X adventure bike.</p>
+            <figure><img src="/images/cb500.jpg" alt="CB 500"><figcaption> Red, 2013 </figcaption></figure>
+            <img src="../../images/cb500.jpg"><img src="data:image/gif;base64,R0lGOD" alt="spacer">
+            <img src="/images/cb500-side.jpg" title="Side view"></body></html>"#;
+        let specs = extractor.specs(html, "Honda", "CB 500", "2013", "https://a/model/honda/cb500.html").value;
+        assert_eq!(specs.len(), 1);
+        let spec = serde_json::to_value(&specs[0]).unwrap();
+        assert_eq!(spec["Title"], "Honda CB 500");
+        assert_eq!(
+            spec["Text"],
+            serde_json::json!(["The CB 500 is a parallel twin aimed at new riders, sold alongside the CBR 500 R and the CB 500 X adventure bike."])
+        );
+        assert_eq!(spec["Rows"][0]["section"], "Engine");
+        assert_eq!(spec["Rows"][1]["section"], "Chassis");
+        assert_eq!(spec["Parsed"]["engine"]["cylinders"], 2);
+        assert_eq!(spec["Parsed"]["engine"]["unparsed"], serde_json::json!([]));
+        assert_eq!(spec["Parsed"]["chassis"]["front_tyre"]["construction"], "radial");
+        assert_eq!(spec["Parsed"]["chassis"]["front_tyre"]["rim"], 17);
+        let images = serde_json::json!([
+            {"url": "https://a/images/cb500.jpg", "alt": "CB 500", "caption": "Red, 2013", "file": null},
+            {"url": "https://a/images/cb500-side.jpg", "alt": null, "caption": "Side view", "file": null},
+        ]);
+        assert_eq!(spec["Images"], images);
+
+        let bad = DEFAULT_PROFILE.replace("variant_overlap = 0.5", "variant_overlap = 2.0");
+        let err = Extractor::new(SiteProfile::parse(&bad).unwrap()).err().unwrap();
+        assert!(err.to_string().contains("`profile.spec.variant_overlap`"), "{}", err);
+    }
+
+    #[test]
+    fn test_custom_profile() {
+        let profile = r#"
+            [brands]
+            selector = "nav a"
+            href_attr = "data-href"
+            [models]
+            selector = "li.model a"
+            href_attr = "href"
+            row = "li"
+            cell = "span"
+            year_index = 0
+            unknown_year = "?"
+            [pagination]
+            selector = "a[rel=next]"
+            href_attr = "href"
+            [spec]
+            row = "dl > div"
+            cell = "dt, dd"
+            cells = 2
+            key_index = 0
+            value_index = 1
+            key = { replace = [{ pattern = ':$', with = "" }] }
+        "#;
+        let extractor = Extractor::new(SiteProfile::parse(profile).unwrap()).unwrap();
+        let brands = extractor.brands(r#"<nav><a data-href="/b/bmw"> BMW </a><a href="/b/ktm">KTM</a></nav>"#, "https://b/");
+        assert_eq!(brands.value.len(), 1);
+        assert_eq!((brands.value[0].get_name(), brands.value[0].get_url()), ("BMW", "https://b/b/bmw"));
+        assert_eq!(brands.diagnostics[0].to_string(), r#"missing_href at 1 "KTM""#);
+        let html = r#"<ul><li class="model"><a href="/m/r1250">R 1250</a><span>2019-</span></li></ul><a rel="next" href="?p=2">2</a>"#;
+        let models = extractor.models(html, "BMW", "https://b/b/bmw").value;
+        assert_eq!((models[0].get_name(), models[0].get_year()), ("R 1250", "2019-"));
+        assert_eq!(extractor.next_page(html, "BMW", "https://b/b/bmw").value.unwrap().get_url(), "https://b/b/bmw?p=2");
+        let specs = extractor.specs("<dl><div><dt>Power:</dt><dd>100 kW</dd></div></dl>", "BMW", "R 1250", "2019", "https://b/m/r1250");
+        assert_eq!(specs.value[0].get_specs()["Power"], vec!["100 kW"]);
+        assert!(specs.diagnostics.is_empty());
+        let empty = extractor.specs("<p>Not found</p>", "BMW", "R 1250", "2019", "https://b/m/r1250");
+        assert_eq!(empty.diagnostics.iter().map(|d| d.get_kind()).collect::<Vec<_>>(), vec![DiagnosticKind::NoSpecs]);
+
+        let err = SiteProfile::parse("[brands]\nselector = 1").unwrap_err();
+        assert!(err.to_string().contains("`profile.brands.selector`"), "{}", err);
+        let bad = profile.replace("nav a", "nav >");
+        let err = Extractor::new(SiteProfile::parse(&bad).unwrap()).err().unwrap();
+        assert!(err.to_string().contains("`profile.brands.selector`"), "{}", err);
+    }
+}
@@ -0,0 +1,260 @@
+use crate::config::{ProgressConfig, ProgressMode};
+use crate::http::StatusError;
+use crate::shutdown::Interrupted;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+// Counters fed by the crawler while it runs.
+#[derive(Debug)]
+pub struct Progress {
+    started: Instant,
+    brands_total: AtomicU64,
+    brands_done: AtomicU64,
+    pages_fetched: AtomicU64,
+    specs_stored: AtomicU64,
+    errors: Mutex<BTreeMap<&'static str, u64>>,
+    // Unfinished jobs of each brand still being crawled, a brand is done when its count drops to zero.
+    brand_jobs: Mutex<HashMap<String, u64>>,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            brands_total: AtomicU64::new(0),
+            brands_done: AtomicU64::new(0),
+            pages_fetched: AtomicU64::new(0),
+            specs_stored: AtomicU64::new(0),
+            errors: Mutex::new(BTreeMap::new()),
+            brand_jobs: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub elapsed: Duration,
+    pub brands_total: u64,
+    pub brands_done: u64,
+    pub pages_fetched: u64,
+    pub specs_stored: u64,
+    pub errors: BTreeMap<&'static str, u64>,
+}
+
+impl Snapshot {
+    pub fn get_rate(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.pages_fetched as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    // Extrapolated from the brands finished so far.
+    pub fn get_eta(&self) -> Option<Duration> {
+        if self.brands_done == 0 || self.brands_done > self.brands_total {
+            return None;
+        }
+        let left = (self.brands_total - self.brands_done) as f64;
+        Some(Duration::from_secs_f64(self.elapsed.as_secs_f64() * left / self.brands_done as f64))
+    }
+
+    fn to_line(&self) -> String {
+        let errors: Vec<String> = self.errors.iter().map(|(k, v)| format!("{} {}", k, v)).collect();
+        format!(
+            "brands {}/{} | pages {} | specs {} | errors {} | {:.1} req/s | eta {}",
+            self.brands_done,
+            self.brands_total,
+            self.pages_fetched,
+            self.specs_stored,
+            if errors.is_empty() { "0".to_owned() } else { errors.join(", ") },
+            self.get_rate(),
+            self.get_eta().map(format_duration).unwrap_or_else(|| "-".to_owned()),
+        )
+    }
+
+    fn to_json(&self) -> String {
+        json!({
+            "elapsed_secs": self.elapsed.as_secs(),
+            "brands_total": self.brands_total,
+            "brands_done": self.brands_done,
+            "pages_fetched": self.pages_fetched,
+            "specs_stored": self.specs_stored,
+            "errors": self.errors,
+            "requests_per_sec": self.get_rate(),
+            "eta_secs": self.get_eta().map(|d| d.as_secs()),
+        })
+        .to_string()
+    }
+}
+
+impl Progress {
+    // Starts tracking a brand, retried pages of brands not tracked are not counted.
+    pub fn add_brand(&self, brand: &str) {
+        if self.brand_jobs.lock().unwrap().insert(brand.to_owned(), 0).is_none() {
+            self.brands_total.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    pub fn job_queued(&self, brand: &str) {
+        if let Some(count) = self.brand_jobs.lock().unwrap().get_mut(brand) {
+            *count += 1;
+        }
+    }
+
+    pub fn job_done(&self, brand: &str) {
+        let mut brand_jobs = self.brand_jobs.lock().unwrap();
+        if let Some(count) = brand_jobs.get_mut(brand) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                brand_jobs.remove(brand);
+                self.brands_done.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    pub fn page_fetched(&self) {
+        self.pages_fetched.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn spec_stored(&self) {
+        self.specs_stored.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn error(&self, e: &(dyn Error + 'static)) {
+        *self.errors.lock().unwrap().entry(error_kind(e)).or_insert(0) += 1;
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            elapsed: self.started.elapsed(),
+            brands_total: self.brands_total.load(Ordering::SeqCst),
+            brands_done: self.brands_done.load(Ordering::SeqCst),
+            pages_fetched: self.pages_fetched.load(Ordering::SeqCst),
+            specs_stored: self.specs_stored.load(Ordering::SeqCst),
+            errors: self.errors.lock().unwrap().clone(),
+        }
+    }
+}
+
+// Coarse error class used for reporting.
+pub fn error_kind(e: &(dyn Error + 'static)) -> &'static str {
+    if e.is::<Interrupted>() {
+        "interrupted"
+    } else if e.is::<StatusError>() {
+        "status"
+    } else if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+        if e.is_timeout() {
+            "timeout"
+        } else if e.is_connect() {
+            "connect"
+        } else if e.is_status() {
+            "status"
+        } else {
+            "http"
+        }
+    } else if e.is::<mongodb::error::Error>() {
+        "database"
+    } else {
+        "other"
+    }
+}
+
+fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+// Prints the progress to stderr every `interval_secs` until finished.
+pub struct Reporter {
+    progress: Arc<Progress>,
+    mode: ProgressMode,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Reporter {
+    pub fn start(progress: Arc<Progress>, config: &ProgressConfig) -> Self {
+        let mode = match config.mode {
+            ProgressMode::Auto if io::stderr().is_terminal() => ProgressMode::Terminal,
+            ProgressMode::Auto => ProgressMode::Json,
+            mode => mode,
+        };
+        let handle = if mode == ProgressMode::Off {
+            None
+        } else {
+            let progress = progress.clone();
+            let interval = Duration::from_secs(config.interval_secs);
+            Some(tokio::spawn(async move {
+                let mut interval = tokio::time::interval(interval);
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    print(mode, &progress.snapshot(), false);
+                }
+            }))
+        };
+        Self { progress, mode, handle }
+    }
+
+    // Stops the periodic output and prints the final state.
+    pub fn finish(self) {
+        if let Some(handle) = self.handle {
+            handle.abort();
+            print(self.mode, &self.progress.snapshot(), true);
+        }
+    }
+}
+
+fn print(mode: ProgressMode, snapshot: &Snapshot, last: bool) {
+    let mut stderr = io::stderr();
+    let _ = match mode {
+        ProgressMode::Terminal if last => writeln!(stderr, "\r\x1b[2K{}", snapshot.to_line()),
+        ProgressMode::Terminal => write!(stderr, "\r\x1b[2K{}", snapshot.to_line()),
+        ProgressMode::Json => writeln!(stderr, "{}", snapshot.to_json()),
+        ProgressMode::Auto | ProgressMode::Off => Ok(()),
+    };
+    let _ = stderr.flush();
+}
+
+#[cfg(test)]
+mod test {
+    use super::Progress;
+    use crate::http::StatusError;
+    use reqwest::StatusCode;
+    use std::time::Duration;
+
+    #[test]
+    fn test_progress() {
+        let progress = Progress::default();
+        for brand in &["honda", "ducati", "bmw", "ktm"] {
+            progress.add_brand(brand);
+        }
+        progress.job_queued("honda");
+        progress.job_queued("honda");
+        progress.job_done("honda");
+        assert_eq!(progress.snapshot().brands_done, 0);
+        progress.job_done("honda");
+        // A retry after the brand finished is not counted again.
+        progress.job_queued("honda");
+        progress.job_done("honda");
+        progress.page_fetched();
+        progress.error(&StatusError {
+            url: "https://a".to_owned(),
+            status: StatusCode::BAD_GATEWAY,
+        });
+        let mut snapshot = progress.snapshot();
+        assert_eq!(snapshot.brands_done, 1);
+        assert_eq!(snapshot.errors.get("status"), Some(&1));
+        snapshot.elapsed = Duration::from_secs(60);
+        assert_eq!(snapshot.get_eta(), Some(Duration::from_secs(180)));
+        assert!(snapshot.to_line().starts_with("brands 1/4 | pages 1 | specs 0 | errors status 1"));
+        assert!(snapshot.to_json().contains("\"brands_done\":1"));
+    }
+}
@@ -0,0 +1,237 @@
+use crate::config::CrawlOrder;
+use crate::result::{Brand, Model};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+#[derive(Debug, Clone)]
+pub enum Job {
+    // First listing page of a brand.
+    Brand(Brand),
+    // A following listing page, `depth` is the number of "Next" links followed to reach it.
+    Listing(Brand, u32),
+    Model(Model),
+    // Pages failed in an earlier pass, with the id of their log entry.
+    RetryListing(Brand, String),
+    RetryModel(Model, String),
+}
+
+impl Job {
+    pub fn get_brand(&self) -> &str {
+        match self {
+            Job::Brand(brand) | Job::Listing(brand, _) | Job::RetryListing(brand, _) => brand.get_name(),
+            Job::Model(model) | Job::RetryModel(model, _) => model.get_brand(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    jobs: VecDeque<Job>,
+    // Jobs queued, kept by a worker or being processed.
+    pending: usize,
+    sealed: bool,
+}
+
+// Job queue shared by the crawl workers, holding at most `capacity` jobs. Seeds are pushed with
+// `push_wait`, which waits while the queue is full. Workers push the jobs they discover through
+// their `WorkerQueue`, which keeps what does not fit on the worker's own stack so a full queue
+// cannot deadlock the pool.
+#[derive(Debug)]
+pub struct JobQueue {
+    state: Mutex<State>,
+    capacity: usize,
+    order: CrawlOrder,
+    jobs_changed: Notify,
+}
+
+impl JobQueue {
+    pub fn new(capacity: usize, order: CrawlOrder) -> Self {
+        Self {
+            state: Mutex::new(State::default()),
+            capacity,
+            order,
+            jobs_changed: Notify::new(),
+        }
+    }
+
+    // Queues a job if there is room, otherwise hands it back. Either way it counts as pending
+    // until `done` is called for it.
+    pub fn push(&self, job: Job) -> Option<Job> {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.pending += 1;
+            if state.jobs.len() >= self.capacity {
+                return Some(job);
+            }
+            self.insert(&mut state, job);
+        }
+        self.jobs_changed.notify_waiters();
+        None
+    }
+
+    pub async fn push_wait(&self, job: Job) {
+        loop {
+            let changed = self.jobs_changed.notified();
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.jobs.len() < self.capacity {
+                    state.pending += 1;
+                    self.insert(&mut state, job);
+                    break;
+                }
+            }
+            changed.await;
+        }
+        self.jobs_changed.notify_waiters();
+    }
+
+    fn insert(&self, state: &mut State, job: Job) {
+        match self.order {
+            CrawlOrder::BreadthFirst => state.jobs.push_back(job),
+            CrawlOrder::DepthFirst => state.jobs.push_front(job),
+        }
+    }
+
+    // Waits for the next job, returns None once the queue is sealed and everything is done.
+    pub async fn pop(&self) -> Option<Job> {
+        loop {
+            let changed = self.jobs_changed.notified();
+            {
+                let mut state = self.state.lock().unwrap();
+                if let Some(job) = state.jobs.pop_front() {
+                    drop(state);
+                    self.jobs_changed.notify_waiters();
+                    return Some(job);
+                }
+                if state.sealed && state.pending == 0 {
+                    return None;
+                }
+            }
+            changed.await;
+        }
+    }
+
+    // Marks a popped job as processed.
+    pub fn done(&self) {
+        self.state.lock().unwrap().pending -= 1;
+        self.jobs_changed.notify_waiters();
+    }
+
+    // No more seeds will be pushed, workers stop once the pending jobs are done.
+    pub fn seal(&self) {
+        self.state.lock().unwrap().sealed = true;
+        self.jobs_changed.notify_waiters();
+    }
+}
+
+// A worker's handle on the shared queue. Jobs it discovers while the queue is full are kept on its
+// own stack, latest first, and the worker runs them before taking another job from the queue.
+#[derive(Debug)]
+pub struct WorkerQueue {
+    queue: Arc<JobQueue>,
+    own: Mutex<Vec<Job>>,
+}
+
+impl WorkerQueue {
+    pub fn new(queue: Arc<JobQueue>) -> Self {
+        Self { queue, own: Mutex::new(Vec::new()) }
+    }
+
+    pub fn push(&self, job: Job) {
+        if let Some(job) = self.queue.push(job) {
+            self.own.lock().unwrap().push(job);
+        }
+    }
+
+    // The next job of the worker, None once the queue is sealed and everything is done.
+    pub async fn pop(&self) -> Option<Job> {
+        let own = self.own.lock().unwrap().pop();
+        match own {
+            Some(job) => Some(job),
+            None => self.queue.pop().await,
+        }
+    }
+
+    pub fn done(&self) {
+        self.queue.done();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Job, JobQueue, WorkerQueue};
+    use crate::config::CrawlOrder;
+    use crate::result::Brand;
+    use std::sync::Arc;
+
+    fn brand(name: &str) -> Job {
+        Job::Brand(Brand::new(name.to_owned(), format!("https://a/bikes/{}.html", name)))
+    }
+
+    fn name(job: Option<Job>) -> String {
+        match job {
+            Some(Job::Brand(b)) => b.get_name().to_owned(),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_order() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let queue = JobQueue::new(10, CrawlOrder::BreadthFirst);
+        queue.push(brand("a"));
+        queue.push(brand("b"));
+        assert_eq!(name(rt.block_on(queue.pop())), "a");
+        let queue = JobQueue::new(10, CrawlOrder::DepthFirst);
+        queue.push(brand("a"));
+        queue.push(brand("b"));
+        assert_eq!(name(rt.block_on(queue.pop())), "b");
+    }
+
+    #[test]
+    fn test_backpressure_and_seal() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let queue = Arc::new(JobQueue::new(1, CrawlOrder::BreadthFirst));
+        rt.block_on(async {
+            let seeder = {
+                let queue = queue.clone();
+                tokio::spawn(async move {
+                    for n in &["a", "b", "c"] {
+                        queue.push_wait(brand(n)).await;
+                    }
+                    queue.seal();
+                })
+            };
+            let mut seen = Vec::new();
+            while let Some(job) = queue.pop().await {
+                assert!(queue.state.lock().unwrap().jobs.len() <= 1);
+                seen.push(name(Some(job)));
+                queue.done();
+            }
+            seeder.await.unwrap();
+            assert_eq!(seen, vec!["a", "b", "c"]);
+        });
+    }
+
+    #[test]
+    fn test_worker_keeps_overflow() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let queue = Arc::new(JobQueue::new(1, CrawlOrder::BreadthFirst));
+        let worker = WorkerQueue::new(queue.clone());
+        for n in &["a", "b", "c"] {
+            worker.push(brand(n));
+        }
+        assert_eq!(queue.state.lock().unwrap().jobs.len(), 1);
+        queue.seal();
+        let seen = rt.block_on(async {
+            let mut seen = Vec::new();
+            while let Some(job) = worker.pop().await {
+                seen.push(name(Some(job)));
+                worker.done();
+            }
+            seen
+        });
+        assert_eq!(seen, vec!["c", "b", "a"]);
+    }
+}
@@ -0,0 +1,407 @@
+use crate::config::ReportConfig;
+use crate::progress::error_kind;
+use crate::result::{Result, Spec};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Collects what happened during a run, turned into a `RunReport` once the run is over.
+#[derive(Debug)]
+pub struct Report {
+    started: Instant,
+    started_at: u64,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    brands_discovered: usize,
+    brands_scraped: BTreeSet<String>,
+    models_per_brand: BTreeMap<String, usize>,
+    failures: BTreeMap<String, u64>,
+    not_spec_pages: BTreeMap<String, u64>,
+    rejected_specs: usize,
+    specs: Vec<SpecSummary>,
+    keys: HashMap<String, u64>,
+    phases: Vec<Phase>,
+    fetches: BTreeMap<String, (u64, Duration)>,
+}
+
+#[derive(Debug, Clone)]
+struct SpecSummary {
+    key: String,
+    brand: String,
+    model: String,
+    year: String,
+    fields: usize,
+    fingerprint: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SparseSpec {
+    pub brand: String,
+    pub model: String,
+    pub year: String,
+    pub fields: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Phase {
+    pub name: String,
+    pub secs: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FetchStats {
+    pub count: u64,
+    pub total_secs: f64,
+    pub avg_secs: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Changes {
+    pub new: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunReport {
+    pub started_at: u64,
+    pub duration_secs: f64,
+    pub brands_discovered: usize,
+    pub brands_scraped: usize,
+    pub models_per_brand: BTreeMap<String, usize>,
+    pub specs_scraped: usize,
+    pub failures_by_kind: BTreeMap<String, u64>,
+    // Specs scraped in this run but rejected by the store. Pages rejected as not being spec pages
+    // are counted in `not_spec_pages`.
+    pub rejected_specs: usize,
+    // Model pages which were not spec pages, by reason.
+    #[serde(default)]
+    pub not_spec_pages: BTreeMap<String, u64>,
+    pub sparse_specs: Vec<SparseSpec>,
+    pub top_keys: Vec<(String, u64)>,
+    // Models compared with the previous report, None when there is none.
+    pub changes: Option<Changes>,
+    // Sanity checks which failed, see `Sanity`.
+    #[serde(default)]
+    pub sanity_violations: Vec<String>,
+    pub phases: Vec<Phase>,
+    pub fetches: BTreeMap<String, FetchStats>,
+    // Content fingerprint of every spec scraped, keyed by "brand / model / year", followed by
+    // " / variant" for the variants of a model.
+    pub fingerprints: BTreeMap<String, String>,
+}
+
+impl Default for Report {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            started_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            state: Mutex::new(State::default()),
+        }
+    }
+}
+
+impl Report {
+    pub fn brands_discovered(&self, n: usize) {
+        self.state.lock().unwrap().brands_discovered += n;
+    }
+
+    pub fn brand_scraped(&self, brand: &str) {
+        self.state.lock().unwrap().brands_scraped.insert(brand.to_owned());
+    }
+
+    pub fn model_found(&self, brand: &str) {
+        *self.state.lock().unwrap().models_per_brand.entry(brand.to_owned()).or_insert(0) += 1;
+    }
+
+    pub fn page_failed(&self, e: &(dyn Error + 'static)) {
+        *self.state.lock().unwrap().failures.entry(error_kind(e).to_owned()).or_insert(0) += 1;
+    }
+
+    pub fn page_not_spec(&self, reason: &str) {
+        *self.state.lock().unwrap().not_spec_pages.entry(reason.to_owned()).or_insert(0) += 1;
+    }
+
+    pub fn spec_rejected(&self) {
+        self.state.lock().unwrap().rejected_specs += 1;
+    }
+
+    pub fn page_fetched(&self, kind: &str, took: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let entry = state.fetches.entry(kind.to_owned()).or_insert((0, Duration::default()));
+        entry.0 += 1;
+        entry.1 += took;
+    }
+
+    // Records a spec as extracted from its page.
+    pub fn spec_scraped(&self, spec: &Spec) {
+        let mut state = self.state.lock().unwrap();
+        for key in spec.get_specs().keys() {
+            *state.keys.entry(key.clone()).or_insert(0) += 1;
+        }
+        state.specs.push(SpecSummary {
+            key: match spec.get_variant() {
+                Some(variant) => format!("{} / {} / {} / {}", spec.get_brand(), spec.get_model(), spec.get_year(), variant),
+                None => format!("{} / {} / {}", spec.get_brand(), spec.get_model(), spec.get_year()),
+            },
+            brand: spec.get_brand().to_owned(),
+            model: spec.get_model().to_owned(),
+            year: spec.get_year().to_owned(),
+            fields: spec.get_specs().len(),
+            fingerprint: fingerprint(spec),
+        });
+    }
+
+    pub fn phase(&self, name: &str, took: Duration) {
+        self.state.lock().unwrap().phases.push(Phase {
+            name: name.to_owned(),
+            secs: took.as_secs_f64(),
+        });
+    }
+
+    pub fn build(&self, config: &ReportConfig, sanity_violations: Vec<String>, previous: Option<&RunReport>) -> RunReport {
+        let state = self.state.lock().unwrap();
+        let mut top_keys: Vec<(String, u64)> = state.keys.iter().map(|(k, n)| (k.clone(), *n)).collect();
+        top_keys.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top_keys.truncate(config.top_keys);
+        let fingerprints: BTreeMap<String, String> = state.specs.iter().map(|s| (s.key.clone(), s.fingerprint.clone())).collect();
+        RunReport {
+            started_at: self.started_at,
+            duration_secs: self.started.elapsed().as_secs_f64(),
+            brands_discovered: state.brands_discovered,
+            brands_scraped: state.brands_scraped.len(),
+            models_per_brand: state.models_per_brand.clone(),
+            specs_scraped: state.specs.len(),
+            failures_by_kind: state.failures.clone(),
+            rejected_specs: state.rejected_specs,
+            not_spec_pages: state.not_spec_pages.clone(),
+            sparse_specs: state
+                .specs
+                .iter()
+                .filter(|s| s.fields < config.few_fields)
+                .map(|s| SparseSpec {
+                    brand: s.brand.clone(),
+                    model: s.model.clone(),
+                    year: s.year.clone(),
+                    fields: s.fields,
+                })
+                .collect(),
+            top_keys,
+            changes: previous.map(|p| diff(&p.fingerprints, &fingerprints)),
+            sanity_violations,
+            phases: state.phases.clone(),
+            fetches: state
+                .fetches
+                .iter()
+                .map(|(kind, (count, total))| {
+                    let stats = FetchStats {
+                        count: *count,
+                        total_secs: total.as_secs_f64(),
+                        avg_secs: total.as_secs_f64() / *count as f64,
+                    };
+                    (kind.clone(), stats)
+                })
+                .collect(),
+            fingerprints,
+        }
+    }
+}
+
+// FNV-1a over the sorted fields, stable across runs and Rust versions.
+fn fingerprint(spec: &Spec) -> String {
+    let mut fields: Vec<_> = spec.get_specs().iter().collect();
+    fields.sort();
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for (k, v) in fields.into_iter().flat_map(|(k, vs)| vs.iter().map(move |v| (k, v))) {
+        for b in k.bytes().chain([0]).chain(v.bytes()).chain([0]) {
+            hash ^= b as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+fn diff(previous: &BTreeMap<String, String>, current: &BTreeMap<String, String>) -> Changes {
+    let mut changes = Changes::default();
+    for (key, fp) in current {
+        match previous.get(key) {
+            None => changes.new.push(key.clone()),
+            Some(prev) if prev != fp => changes.changed.push(key.clone()),
+            Some(_) => {}
+        }
+    }
+    changes.removed = previous.keys().filter(|k| !current.contains_key(*k)).cloned().collect();
+    changes
+}
+
+// Reads the report of the previous run, a missing or unreadable file means there is none.
+pub fn load_previous(path: &str) -> Option<RunReport> {
+    if path.is_empty() {
+        return None;
+    }
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+impl RunReport {
+    // Writes the JSON and HTML files enabled in the config.
+    pub fn write(&self, config: &ReportConfig) -> Result<()> {
+        if !config.json_path.is_empty() {
+            write_file(&config.json_path, &serde_json::to_string_pretty(self)?)?;
+        }
+        if !config.html_path.is_empty() {
+            write_file(&config.html_path, &self.to_html())?;
+        }
+        Ok(())
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "Run report");
+        let _ = writeln!(out, "  duration: {:.1}s", self.duration_secs);
+        for phase in &self.phases {
+            let _ = writeln!(out, "    {}: {:.1}s", phase.name, phase.secs);
+        }
+        for (kind, stats) in &self.fetches {
+            let _ = writeln!(out, "    {} fetches: {} in {:.1}s, {:.3}s on average", kind, stats.count, stats.total_secs, stats.avg_secs);
+        }
+        let _ = writeln!(out, "  brands: {} discovered, {} scraped", self.brands_discovered, self.brands_scraped);
+        let _ = writeln!(out, "  models: {} found, {} specs scraped", self.models_per_brand.values().sum::<usize>(), self.specs_scraped);
+        let failures: Vec<String> = self.failures_by_kind.iter().map(|(k, n)| format!("{} {}", k, n)).collect();
+        let _ = writeln!(out, "  failed pages: {}", if failures.is_empty() { "none".to_owned() } else { failures.join(", ") });
+        let _ = writeln!(out, "  rejected specs: {}", self.rejected_specs);
+        let not_spec: Vec<String> = self.not_spec_pages.iter().map(|(r, n)| format!("{} {}", r, n)).collect();
+        let _ = writeln!(out, "  not spec pages: {}", if not_spec.is_empty() { "none".to_owned() } else { not_spec.join(", ") });
+        for violation in &self.sanity_violations {
+            let _ = writeln!(out, "  sanity check failed: {}", violation);
+        }
+        let _ = writeln!(out, "  specs with few fields: {}", self.sparse_specs.len());
+        let keys: Vec<String> = self.top_keys.iter().take(10).map(|(k, n)| format!("{} ({})", k, n)).collect();
+        let _ = writeln!(out, "  most common keys: {}", keys.join(", "));
+        match &self.changes {
+            Some(c) => {
+                let _ = writeln!(out, "  since the previous run: {} new, {} changed, {} removed", c.new.len(), c.changed.len(), c.removed.len());
+            }
+            None => {
+                let _ = writeln!(out, "  no previous run to compare with");
+            }
+        }
+        out
+    }
+
+    fn to_html(&self) -> String {
+        let mut out = String::from("<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Run report</title></head><body>\n");
+        let _ = writeln!(out, "<h1>Run report</h1>\n<pre>{}</pre>", escape(&self.to_text()));
+        table(
+            &mut out,
+            "Models per brand",
+            &["Brand", "Models"],
+            self.models_per_brand.iter().map(|(b, n)| vec![b.clone(), n.to_string()]),
+        );
+        table(&mut out, "Failed pages", &["Error", "Pages"], self.failures_by_kind.iter().map(|(k, n)| vec![k.clone(), n.to_string()]));
+        table(
+            &mut out,
+            "Not spec pages",
+            &["Reason", "Pages"],
+            self.not_spec_pages.iter().map(|(r, n)| vec![r.clone(), n.to_string()]),
+        );
+        table(
+            &mut out,
+            "Specs with few fields",
+            &["Brand", "Model", "Year", "Fields"],
+            self.sparse_specs.iter().map(|s| vec![s.brand.clone(), s.model.clone(), s.year.clone(), s.fields.to_string()]),
+        );
+        table(&mut out, "Most common keys", &["Key", "Specs"], self.top_keys.iter().map(|(k, n)| vec![k.clone(), n.to_string()]));
+        if let Some(changes) = &self.changes {
+            for (title, models) in &[("New models", &changes.new), ("Changed models", &changes.changed), ("Removed models", &changes.removed)] {
+                table(&mut out, title, &["Model"], models.iter().map(|m| vec![m.clone()]));
+            }
+        }
+        out.push_str("</body></html>\n");
+        out
+    }
+}
+
+fn table(out: &mut String, title: &str, header: &[&str], rows: impl Iterator<Item = Vec<String>>) {
+    let _ = writeln!(out, "<h2>{}</h2>\n<table>", escape(title));
+    let _ = writeln!(out, "<tr>{}</tr>", header.iter().map(|h| format!("<th>{}</th>", escape(h))).collect::<String>());
+    for row in rows {
+        let _ = writeln!(out, "<tr>{}</tr>", row.iter().map(|c| format!("<td>{}</td>", escape(c))).collect::<String>());
+    }
+    out.push_str("</table>\n");
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn write_file(path: &str, content: &str) -> Result<()> {
+    if let Some(dir) = Path::new(path).parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, content)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::Report;
+    use crate::config::ReportConfig;
+    use crate::result::{Spec, SpecRow};
+
+    fn spec(model: &str, fields: &[(&str, &str)]) -> Spec {
+        let mut spec = Spec::new("Honda".to_owned(), model.to_owned(), "2020".to_owned());
+        for (i, (k, v)) in fields.iter().enumerate() {
+            spec.add_row(SpecRow::new(i, None, k.to_string(), v.to_string()));
+        }
+        spec
+    }
+
+    #[test]
+    fn test_report() {
+        let config = ReportConfig {
+            few_fields: 2,
+            ..ReportConfig::default()
+        };
+        let previous = Report::default();
+        previous.spec_scraped(&spec("CB 500", &[("Power", "35 kW")]));
+        previous.spec_scraped(&spec("CBR 600", &[("Power", "88 kW")]));
+        previous.spec_scraped(&spec("VFR 800", &[("Power", "79 kW")]));
+        let previous = previous.build(&config, Vec::new(), None);
+        assert_eq!(previous.changes, None);
+
+        let report = Report::default();
+        report.brands_discovered(3);
+        report.brand_scraped("Honda");
+        report.model_found("Honda");
+        report.model_found("Honda");
+        report.page_failed(&*Box::<dyn std::error::Error>::from("boom"));
+        report.spec_rejected();
+        report.spec_scraped(&spec("CB 500", &[("Power", "35 kW")]));
+        report.spec_scraped(&spec("CBR 600", &[("Power", "90 kW"), ("Torque", "66 Nm")]));
+        report.spec_scraped(&spec("NC 750", &[("Power", "43 kW"), ("Torque", "69 Nm")]));
+        let report = report.build(&config, Vec::new(), Some(&previous));
+        assert_eq!((report.brands_discovered, report.brands_scraped, report.specs_scraped), (3, 1, 3));
+        assert_eq!(report.models_per_brand.get("Honda"), Some(&2));
+        assert_eq!(report.failures_by_kind.get("other"), Some(&1));
+        assert_eq!(report.rejected_specs, 1);
+        assert_eq!(report.sparse_specs.len(), 1);
+        assert_eq!(report.top_keys[0], ("Power".to_owned(), 3));
+        let changes = report.changes.clone().unwrap();
+        assert_eq!(changes.new, vec!["Honda / NC 750 / 2020"]);
+        assert_eq!(changes.changed, vec!["Honda / CBR 600 / 2020"]);
+        assert_eq!(changes.removed, vec!["Honda / VFR 800 / 2020"]);
+
+        let json = serde_json::to_string(&report).unwrap();
+        assert_eq!(serde_json::from_str::<super::RunReport>(&json).unwrap(), report);
+        assert!(report.to_html().contains("<td>Honda / NC 750 / 2020</td>"));
+    }
+}
@@ -1,17 +1,34 @@
+use crate::chassis::Chassis;
+use crate::diagnostic::Diagnostic;
+use crate::engine::Engine;
+use crate::image::ImageRef;
+use crate::year::YearRange;
+use serde::ser::{SerializeMap, Serializer};
+use serde::Serialize;
 use std::collections::HashMap;
 use std::error::Error;
 
 pub type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
 
+#[allow(clippy::enum_variant_names)]
 pub enum Log<T, E> {
     Log(T),
     Err(T, E),
+    // Not fetched on purpose, with the reason.
+    Skip(T, String),
+    // Unfinished because the run was stopped, picked up again by the next run.
+    Interrupt(T),
+    // Fetched but not a spec page, with the reason.
+    Reject(T, String),
+    // What the extractors skipped or could not read on a fetched page, whatever its outcome.
+    Diagnose(T, Vec<Diagnostic>),
 }
 
+#[derive(Debug)]
 pub enum LogLevel {
     Brand(Brand),
     Model(Model),
-    Spec(Spec),
+    Spec(Box<Spec>),
 }
 
 #[derive(Debug, Clone)]
@@ -38,13 +55,18 @@ impl Brand {
 pub struct Model {
     brand: String,
     name: String,
-    year: String,
+    year: YearRange,
     url: String,
 }
 
 impl Model {
     pub fn new(brand: String, name: String, year: String, url: String) -> Self {
-        Self { brand, name, year, url }
+        Self {
+            brand,
+            name,
+            year: YearRange::parse(&year),
+            url,
+        }
     }
 
     pub fn get_brand(&self) -> &str {
@@ -55,7 +77,12 @@ impl Model {
         &self.name
     }
 
+    // The year column as listed.
     pub fn get_year(&self) -> &str {
+        self.year.get_raw()
+    }
+
+    pub fn get_years(&self) -> &YearRange {
         &self.year
     }
 
@@ -64,12 +91,77 @@ impl Model {
     }
 }
 
+// A row of the spec table as it appears on the page, `position` counts every row of the table and
+// `section` is the heading above the row, e.g. "Engine" or "Dimensions". The text of the key and the
+// value as found in the html is kept when cleaning it up changed it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SpecRow {
+    position: usize,
+    section: Option<String>,
+    key: String,
+    value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    raw_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    raw_value: Option<String>,
+}
+
+impl SpecRow {
+    pub fn new(position: usize, section: Option<String>, key: String, value: String) -> Self {
+        Self {
+            position,
+            section,
+            key,
+            value,
+            raw_key: None,
+            raw_value: None,
+        }
+    }
+
+    pub fn with_raw(mut self, raw_key: &str, raw_value: &str) -> Self {
+        self.raw_key = Some(raw_key.to_owned()).filter(|raw| *raw != self.key);
+        self.raw_value = Some(raw_value.to_owned()).filter(|raw| *raw != self.value);
+        self
+    }
+}
+
+// Fields read from the text of some rows, stored beside the rows.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Parsed {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub engine: Option<Engine>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chassis: Option<Box<Chassis>>,
+}
+
+impl Parsed {
+    fn is_empty(&self) -> bool {
+        self.engine.is_none() && self.chassis.is_none()
+    }
+}
+
+// Fields of the stored document which are not spec rows. A row of the page with one of these keys
+// is kept in "Rows" only, the extractor reports it as a `reserved_key` diagnostic.
+pub const RESERVED_KEYS: [&str; 11] = ["Brand", "Model", "Variant", "Source", "Key", "Years", "Rows", "Title", "Text", "Images", "Parsed"];
+
 #[derive(Debug, Clone)]
 pub struct Spec {
     brand: String,
     model: String,
-    year: String,
-    specs: HashMap<String, String>,
+    year: YearRange,
+    // Set when the page of the model describes several variants, e.g. "ABS" or "US".
+    variant: Option<String>,
+    // The values of every key in page order, several for a key repeated on the page.
+    specs: HashMap<String, Vec<String>>,
+    rows: Vec<SpecRow>,
+    // The title of the page, its blocks of descriptive text and its images.
+    title: Option<String>,
+    text: Vec<String>,
+    images: Vec<ImageRef>,
+    parsed: Parsed,
+    // The site the spec was scraped from and the key shared by the specs of the same model.
+    source: Option<String>,
+    merge_key: Option<String>,
 }
 
 impl Spec {
@@ -77,16 +169,51 @@ impl Spec {
         Self {
             brand,
             model,
-            year,
+            year: YearRange::parse(&year),
+            variant: None,
             specs: HashMap::new(),
+            rows: vec![],
+            title: None,
+            text: vec![],
+            images: vec![],
+            parsed: Parsed::default(),
+            source: None,
+            merge_key: None,
         }
     }
 
-    pub fn add_spec(&mut self, key: String, val: String) {
-        self.specs.insert(key, val);
+    // Adds a row of the spec table, a key seen before gets one more value. The key becomes a field
+    // name of the stored document, where a dot would be read as a path, so the dots of decimals such
+    // as "2.5 in" are written as commas there. The row keeps the key as it is.
+    pub fn add_row(&mut self, row: SpecRow) {
+        self.specs.entry(row.key.replace('.', ",")).or_default().push(row.value.clone());
+        self.rows.push(row);
+    }
+
+    pub fn set_page(&mut self, title: Option<String>, text: Vec<String>, images: Vec<ImageRef>) {
+        self.title = title;
+        self.text = text;
+        self.images = images;
+    }
+
+    pub fn get_images(&self) -> &[ImageRef] {
+        &self.images
+    }
+
+    pub fn set_images(&mut self, images: Vec<ImageRef>) {
+        self.images = images;
+    }
+
+    pub fn set_parsed(&mut self, parsed: Parsed) {
+        self.parsed = parsed;
+    }
+
+    pub fn set_source(&mut self, source: String, merge_key: String) {
+        self.source = Some(source);
+        self.merge_key = Some(merge_key);
     }
 
-    pub fn get_specs(&self) -> &HashMap<String, String> {
+    pub fn get_specs(&self) -> &HashMap<String, Vec<String>> {
         &self.specs
     }
 
@@ -99,6 +226,56 @@ impl Spec {
     }
 
     pub fn get_year(&self) -> &str {
-        &self.year
+        self.year.get_raw()
+    }
+
+    pub fn set_variant(&mut self, variant: String) {
+        self.variant = Some(variant);
+    }
+
+    pub fn get_variant(&self) -> Option<&str> {
+        self.variant.as_deref()
+    }
+}
+
+// Stored as its fields, a key repeated on the page holding an array of its values, followed by its
+// "Brand", "Model", "Variant", "Source" and merge "Key", the "Years" as {raw, start, end}, queried as e.g. {"Years.start": {"$lte": 2017}} with a null end while
+// in production, the "Rows" in page order, the page "Title", its "Text" blocks, its "Images" and the
+// fields "Parsed" from the rows.
+impl Serialize for Spec {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        for (key, vals) in self.specs.iter().filter(|(k, _)| !RESERVED_KEYS.contains(&k.as_str())) {
+            match vals.as_slice() {
+                [val] => map.serialize_entry(key, val)?,
+                vals => map.serialize_entry(key, vals)?,
+            }
+        }
+        map.serialize_entry("Brand", &self.brand)?;
+        map.serialize_entry("Model", &self.model)?;
+        if let Some(variant) = &self.variant {
+            map.serialize_entry("Variant", variant)?;
+        }
+        if let Some(source) = &self.source {
+            map.serialize_entry("Source", source)?;
+        }
+        if let Some(merge_key) = &self.merge_key {
+            map.serialize_entry("Key", merge_key)?;
+        }
+        map.serialize_entry("Years", &self.year)?;
+        map.serialize_entry("Rows", &self.rows)?;
+        if let Some(title) = &self.title {
+            map.serialize_entry("Title", title)?;
+        }
+        if !self.text.is_empty() {
+            map.serialize_entry("Text", &self.text)?;
+        }
+        if !self.images.is_empty() {
+            map.serialize_entry("Images", &self.images)?;
+        }
+        if !self.parsed.is_empty() {
+            map.serialize_entry("Parsed", &self.parsed)?;
+        }
+        map.end()
     }
 }
@@ -0,0 +1,198 @@
+use crate::config::{SanityAction, SanityConfig};
+use crate::result::Spec;
+use crate::shutdown::Shutdown;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::{error, warn};
+
+// The sanity checks which failed in a run configured to abort.
+#[derive(Debug)]
+pub struct Breach(pub Vec<String>);
+
+impl fmt::Display for Breach {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sanity checks failed, the site layout may have changed: {}", self.0.join("; "))
+    }
+}
+
+impl Error for Breach {}
+
+#[derive(Debug, Default)]
+struct State {
+    specs: u64,
+    empty: u64,
+    // Specs with too few fields or without a core key, empty ones included.
+    poor: u64,
+    saved: usize,
+    breached: HashSet<&'static str>,
+    violations: Vec<String>,
+}
+
+// Catches extractors silently returning nothing after a markup change. Every breached threshold is
+// reported once; with `action = "abort"` the first one also stops the crawl through the shutdown.
+#[derive(Debug)]
+pub struct Sanity {
+    config: SanityConfig,
+    shutdown: Shutdown,
+    state: Mutex<State>,
+}
+
+impl Sanity {
+    pub fn new(config: &SanityConfig, shutdown: Shutdown) -> Self {
+        Self {
+            config: config.clone(),
+            shutdown,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    pub fn check_brands(&self, count: usize, url: &str, html: &str) {
+        if count < self.config.min_brands {
+            self.save(url, html);
+            self.breach("min_brands", format!("found {} brands on {}, expected at least {}", count, url, self.config.min_brands));
+        }
+    }
+
+    // Checks a spec as extracted from its page, before any field is added by the crawler.
+    pub fn check_spec(&self, spec: &Spec, url: &str, html: &str) {
+        let fields = spec.get_specs();
+        let missing: Vec<&str> = self.config.core_keys.iter().filter(|k| !fields.keys().any(|f| f.eq_ignore_ascii_case(k))).map(|k| k.as_str()).collect();
+        let poor = fields.len() < self.config.min_fields || !missing.is_empty();
+        let (specs, empty_ratio, poor_ratio) = {
+            let mut state = self.state.lock().unwrap();
+            state.specs += 1;
+            if fields.is_empty() {
+                state.empty += 1;
+            }
+            if poor {
+                state.poor += 1;
+            }
+            (state.specs, state.empty as f64 / state.specs as f64, state.poor as f64 / state.specs as f64)
+        };
+        if poor {
+            warn!(fields = fields.len(), missing = ?missing, "spec looks incomplete");
+            self.save(url, html);
+        }
+        if specs < self.config.min_sample {
+            return;
+        }
+        if empty_ratio > self.config.max_empty_ratio {
+            self.breach("max_empty_ratio", format!("{:.0}% of model pages had no spec fields", empty_ratio * 100.0));
+        }
+        if poor_ratio > self.config.max_poor_ratio {
+            self.breach(
+                "max_poor_ratio",
+                format!(
+                    "{:.0}% of specs had fewer than {} fields or lacked one of {:?}",
+                    poor_ratio * 100.0,
+                    self.config.min_fields,
+                    self.config.core_keys
+                ),
+            );
+        }
+    }
+
+    // Records a failed check, ratios are checked after every spec so each check is reported once.
+    fn breach(&self, check: &'static str, violation: String) {
+        let mut state = self.state.lock().unwrap();
+        if !state.breached.insert(check) {
+            return;
+        }
+        error!(check, violation = %violation, "sanity check failed");
+        state.violations.push(violation);
+        if self.config.action == SanityAction::Abort {
+            self.shutdown.trigger();
+        }
+    }
+
+    // Keeps a copy of an offending page in `dump_dir`, up to `max_saved_pages` of them.
+    fn save(&self, url: &str, html: &str) {
+        if self.config.dump_dir.is_empty() {
+            return;
+        }
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.saved >= self.config.max_saved_pages {
+                return;
+            }
+            state.saved += 1;
+        }
+        let name: String = url
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' { c } else { '_' })
+            .collect();
+        let path = Path::new(&self.config.dump_dir).join(name);
+        if let Err(e) = fs::create_dir_all(&self.config.dump_dir).and_then(|_| fs::write(&path, html)) {
+            warn!(error = %e, path = %path.display(), "failed to save page for inspection");
+        }
+    }
+
+    pub fn get_violations(&self) -> Vec<String> {
+        self.state.lock().unwrap().violations.clone()
+    }
+
+    // Fails a run configured to abort if any check failed.
+    pub fn check(&self) -> std::result::Result<(), Breach> {
+        let violations = self.get_violations();
+        if self.config.action == SanityAction::Abort && !violations.is_empty() {
+            return Err(Breach(violations));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Sanity;
+    use crate::config::{SanityAction, SanityConfig};
+    use crate::result::{Spec, SpecRow};
+    use crate::shutdown::Shutdown;
+    use std::fs;
+
+    fn spec(fields: &[&str]) -> Spec {
+        let mut spec = Spec::new("Honda".to_owned(), "CB 500".to_owned(), "2013".to_owned());
+        for (i, f) in fields.iter().enumerate() {
+            spec.add_row(SpecRow::new(i, None, f.to_string(), "x".to_owned()));
+        }
+        spec
+    }
+
+    #[test]
+    fn test_sanity() {
+        let dump_dir = std::env::temp_dir().join(format!("moto_spec_sanity_{}", std::process::id()));
+        let config = SanityConfig {
+            min_brands: 2,
+            min_fields: 2,
+            core_keys: vec!["Engine".to_owned()],
+            min_sample: 4,
+            max_empty_ratio: 0.3,
+            max_poor_ratio: 0.9,
+            action: SanityAction::Abort,
+            dump_dir: dump_dir.to_str().unwrap().to_owned(),
+            max_saved_pages: 2,
+        };
+        let shutdown = Shutdown::new();
+        let sanity = Sanity::new(&config, shutdown.clone());
+        sanity.check_brands(5, "https://a/index.htm", "");
+        sanity.check_spec(&spec(&["engine", "Power"]), "https://a/model/1.html", "");
+        sanity.check_spec(&spec(&[]), "https://a/model/2.html", "<p>2</p>");
+        sanity.check_spec(&spec(&["Power", "Torque"]), "https://a/model/3.html", "<p>3</p>");
+        assert!(sanity.check().is_ok());
+        assert!(!shutdown.is_triggered());
+        sanity.check_spec(&spec(&[]), "https://a/model/4.html", "<p>4</p>");
+        sanity.check_spec(&spec(&[]), "https://a/model/5.html", "<p>5</p>");
+        assert!(shutdown.is_triggered());
+        assert_eq!(sanity.get_violations(), vec!["50% of model pages had no spec fields"]);
+        assert!(sanity.check().is_err());
+        let mut saved: Vec<String> = fs::read_dir(&dump_dir).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+        saved.sort();
+        fs::remove_dir_all(&dump_dir).unwrap();
+        assert_eq!(saved, vec!["a_model_2.html", "a_model_3.html"]);
+    }
+}
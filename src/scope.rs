@@ -0,0 +1,141 @@
+use crate::config::{ConfigError, ScopeConfig};
+use crate::result::{Brand, Model};
+use crate::year::YearRange;
+use regex::Regex;
+use std::collections::HashMap;
+
+// Decides which brands, models and urls a crawl is allowed to visit.
+#[derive(Debug, Clone)]
+pub struct Scope {
+    brands: Vec<String>,
+    exclude_brands: Vec<String>,
+    aliases: HashMap<String, String>,
+    min_year: Option<i32>,
+    max_year: Option<i32>,
+    include_unknown_years: bool,
+    include_urls: Vec<Regex>,
+    exclude_urls: Vec<Regex>,
+}
+
+// The scope of the default config, which allows everything.
+impl Default for Scope {
+    fn default() -> Self {
+        Self::new(&ScopeConfig::default()).unwrap()
+    }
+}
+
+impl Scope {
+    pub fn new(config: &ScopeConfig) -> std::result::Result<Self, ConfigError> {
+        let mut scope = Self {
+            brands: Vec::new(),
+            exclude_brands: Vec::new(),
+            aliases: config.brand_aliases.iter().map(|(k, v)| (normalize_brand(k), normalize_brand(v))).collect(),
+            min_year: config.min_year,
+            max_year: config.max_year,
+            include_unknown_years: config.include_unknown_years,
+            include_urls: compile("scope.include_urls", &config.include_urls)?,
+            exclude_urls: compile("scope.exclude_urls", &config.exclude_urls)?,
+        };
+        scope.brands = config.brands.iter().map(|b| scope.canonical(b)).collect();
+        scope.exclude_brands = config.exclude_brands.iter().map(|b| scope.canonical(b)).collect();
+        Ok(scope)
+    }
+
+    fn canonical(&self, name: &str) -> String {
+        let name = normalize_brand(name);
+        self.aliases.get(&name).cloned().unwrap_or(name)
+    }
+
+    // Identifies a bike across sites, brands are resolved through the aliases.
+    pub fn merge_key(&self, brand: &str, model: &str, year: &YearRange) -> String {
+        format!("{}/{}/{}", self.canonical(brand), normalize_brand(model), year.to_key())
+    }
+
+    fn allows_brand_name(&self, name: &str) -> bool {
+        let name = self.canonical(name);
+        (self.brands.is_empty() || self.brands.contains(&name)) && !self.exclude_brands.contains(&name)
+    }
+
+    pub fn allows_brand(&self, brand: &Brand) -> bool {
+        self.allows_brand_name(brand.get_name()) && !self.is_excluded_url(brand.get_url())
+    }
+
+    // Include patterns only restrict model pages, brand and listing pages are governed by the brand filters.
+    pub fn allows_page(&self, url: &str) -> bool {
+        !self.is_excluded_url(url)
+    }
+
+    pub fn allows_model(&self, model: &Model) -> bool {
+        if !self.allows_brand_name(model.get_brand()) || self.is_excluded_url(model.get_url()) || !self.include_urls.is_empty() && !self.include_urls.iter().any(|r| r.is_match(model.get_url())) {
+            return false;
+        }
+        self.allows_year(model.get_years())
+    }
+
+    pub fn allows_year(&self, year: &YearRange) -> bool {
+        if self.min_year.is_none() && self.max_year.is_none() {
+            return true;
+        }
+        if !year.is_known() {
+            return self.include_unknown_years;
+        }
+        year.overlaps(self.min_year, self.max_year)
+    }
+
+    fn is_excluded_url(&self, url: &str) -> bool {
+        self.exclude_urls.iter().any(|r| r.is_match(url))
+    }
+}
+
+fn compile(key: &str, patterns: &[String]) -> std::result::Result<Vec<Regex>, ConfigError> {
+    patterns
+        .iter()
+        .enumerate()
+        .map(|(i, p)| Regex::new(p).map_err(|e| ConfigError::new(&format!("{}[{}]", key, i), e.to_string())))
+        .collect()
+}
+
+// Brand names are compared case-insensitively and without punctuation, so "MV-Agusta" equals "mv agusta".
+fn normalize_brand(name: &str) -> String {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::Scope;
+    use crate::config::ScopeConfig;
+    use crate::result::{Brand, Model};
+    use crate::year::YearRange;
+
+    #[test]
+    fn test_scope() {
+        let config = ScopeConfig {
+            brands: vec!["MV".to_owned(), "honda".to_owned()],
+            brand_aliases: vec![("MV".to_owned(), "MV Agusta".to_owned())].into_iter().collect(),
+            min_year: Some(2010),
+            max_year: Some(2015),
+            exclude_urls: vec!["gallery".to_owned()],
+            ..ScopeConfig::default()
+        };
+        let scope = Scope::new(&config).unwrap();
+        assert!(scope.allows_brand(&Brand::new("MV-Agusta".to_owned(), "https://a/bikes/mv_agusta.html".to_owned())));
+        assert!(scope.allows_brand(&Brand::new("HONDA".to_owned(), "https://a/bikes/honda.html".to_owned())));
+        assert!(!scope.allows_brand(&Brand::new("Yamaha".to_owned(), "https://a/bikes/yamaha.html".to_owned())));
+        let model = |year: &str, url: &str| Model::new("Honda".to_owned(), "CB".to_owned(), year.to_owned(), url.to_owned());
+        assert!(scope.allows_model(&model("2008 - 2012", "https://a/model/honda/cb.html")));
+        assert!(!scope.allows_model(&model("2016", "https://a/model/honda/cb.html")));
+        assert!(!scope.allows_model(&model("2012", "https://a/model/honda/gallery.html")));
+        assert!(scope.allows_model(&model("unknown", "https://a/model/honda/cb.html")));
+        assert!(scope.allows_model(&model("2014 on", "https://a/model/honda/cb.html")));
+        assert!(!scope.allows_model(&model("2016 on", "https://a/model/honda/cb.html")));
+        assert!(Scope::default().allows_model(&model("unknown", "https://a/model/honda/cb.html")));
+        assert_eq!(
+            scope.merge_key("MV", "F4 1000-R", &YearRange::parse("2010 - 12")),
+            scope.merge_key("mv agusta", "f4 1000 r", &YearRange::parse("2010-2012"))
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use crate::result::LogLevel;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+// Returned instead of starting a request once a shutdown has been triggered.
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "interrupted by shutdown")
+    }
+}
+
+impl Error for Interrupted {}
+
+// Shared stop flag plus a registry of the items currently being worked on, so whatever is still
+// in flight when the grace period runs out can be marked for the next run.
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    triggered: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+    next_id: Arc<AtomicU64>,
+    in_flight: Arc<Mutex<HashMap<u64, LogLevel>>>,
+}
+
+pub struct InFlight {
+    id: u64,
+    in_flight: Arc<Mutex<HashMap<u64, LogLevel>>>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.in_flight.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn trigger(&self) {
+        self.triggered.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.triggered.load(Ordering::SeqCst)
+    }
+
+    pub async fn wait(&self) {
+        let notified = self.notify.notified();
+        if self.is_triggered() {
+            return;
+        }
+        notified.await;
+    }
+
+    // Triggers the shutdown on SIGINT or SIGTERM.
+    pub fn listen(&self) {
+        let shutdown = self.clone();
+        tokio::spawn(async move {
+            #[cfg(unix)]
+            {
+                use tokio::signal::unix::{signal, SignalKind};
+                match signal(SignalKind::terminate()) {
+                    Ok(mut term) => {
+                        tokio::select! {
+                            _ = tokio::signal::ctrl_c() => {},
+                            _ = term.recv() => {},
+                        }
+                    }
+                    Err(_) => {
+                        let _ = tokio::signal::ctrl_c().await;
+                    }
+                }
+            }
+            #[cfg(not(unix))]
+            {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+            shutdown.trigger();
+        });
+    }
+
+    // Registers an item as in flight until the returned guard is dropped.
+    pub fn track(&self, level: LogLevel) -> InFlight {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        self.in_flight.lock().unwrap().insert(id, level);
+        InFlight {
+            id,
+            in_flight: self.in_flight.clone(),
+        }
+    }
+
+    // Removes and returns everything still in flight.
+    pub fn drain_in_flight(&self) -> Vec<LogLevel> {
+        self.in_flight.lock().unwrap().drain().map(|(_, level)| level).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Shutdown;
+    use crate::result::{Brand, LogLevel};
+
+    #[test]
+    fn test_shutdown() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let shutdown = Shutdown::new();
+        let waiter = {
+            let shutdown = shutdown.clone();
+            rt.spawn(async move { shutdown.wait().await })
+        };
+        let done = shutdown.track(LogLevel::Brand(Brand::new("a".to_owned(), "https://a".to_owned())));
+        let _pending = shutdown.track(LogLevel::Brand(Brand::new("b".to_owned(), "https://b".to_owned())));
+        drop(done);
+        shutdown.trigger();
+        rt.block_on(waiter).unwrap();
+        assert!(shutdown.is_triggered());
+        let pending = shutdown.drain_in_flight();
+        assert_eq!(pending.len(), 1);
+        assert!(matches!(&pending[0], LogLevel::Brand(b) if b.get_name() == "b"));
+    }
+}
@@ -0,0 +1,148 @@
+use crate::classify::PageClass;
+use crate::config::SiteConfig;
+use crate::diagnostic::Extracted;
+use crate::profile::Extractor;
+use crate::result::{Brand, Model, Result, Spec};
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use url::Url;
+
+// A page on a host none of the configured sites crawls, e.g. a log entry of a removed source.
+#[derive(Debug)]
+pub struct UnknownSite(pub String);
+
+impl fmt::Display for UnknownSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no configured site handles {}", self.0)
+    }
+}
+
+impl Error for UnknownSite {}
+
+// A source of motorcycle specs. The crawler fetches the pages, stores the specs and handles retries,
+// the site only turns its pages into brands, models and specs. Each extractor also returns what it
+// skipped or could not read on the page, stored by the logger.
+pub trait Site: Send + Sync {
+    // Stored with every spec as its source.
+    fn get_name(&self) -> &str;
+    // The page listing every brand, where the crawl of the site starts.
+    fn get_index_url(&self) -> &str;
+    fn extract_brands(&self, html: &str) -> Extracted<Vec<Brand>>;
+    fn extract_models(&self, html: &str, page: &Brand) -> Extracted<Vec<Model>>;
+    fn extract_next_page(&self, html: &str, page: &Brand) -> Extracted<Option<Brand>>;
+    // One spec per variant described on the page, at least one.
+    fn extract_specs(&self, html: &str, model: &Model) -> Extracted<Vec<Spec>>;
+    // Whether a model page is a spec page, the specs of other pages are never stored.
+    fn classify(&self, page: &Extracted<Vec<Spec>>) -> PageClass;
+
+    // Whether a page belongs to the site, pages of failed runs are retried by the site owning them.
+    fn owns(&self, url: &str) -> bool {
+        host(url).is_some() && host(url) == host(self.get_index_url())
+    }
+}
+
+pub(crate) fn host(url: &str) -> Option<String> {
+    Url::parse(url).ok()?.host_str().map(|h| h.to_lowercase())
+}
+
+// A site whose pages are described by a `SiteProfile`.
+pub struct ProfileSite {
+    name: String,
+    index_url: String,
+    extractor: Extractor,
+}
+
+impl ProfileSite {
+    pub fn new(name: &str, index_url: &str, extractor: Extractor) -> Self {
+        Self {
+            name: name.to_owned(),
+            index_url: index_url.to_owned(),
+            extractor,
+        }
+    }
+}
+
+impl Site for ProfileSite {
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn get_index_url(&self) -> &str {
+        &self.index_url
+    }
+
+    fn extract_brands(&self, html: &str) -> Extracted<Vec<Brand>> {
+        self.extractor.brands(html, &self.index_url)
+    }
+
+    fn extract_models(&self, html: &str, page: &Brand) -> Extracted<Vec<Model>> {
+        self.extractor.models(html, page.get_name(), page.get_url())
+    }
+
+    fn extract_next_page(&self, html: &str, page: &Brand) -> Extracted<Option<Brand>> {
+        self.extractor.next_page(html, page.get_name(), page.get_url())
+    }
+
+    fn extract_specs(&self, html: &str, model: &Model) -> Extracted<Vec<Spec>> {
+        self.extractor.specs(html, model.get_brand(), model.get_name(), model.get_year(), model.get_url())
+    }
+
+    fn classify(&self, page: &Extracted<Vec<Spec>>) -> PageClass {
+        self.extractor.classify(page)
+    }
+}
+
+// The sites of a run, crawled in order.
+pub struct Sites(Vec<Arc<dyn Site>>);
+
+impl Sites {
+    pub fn new(sites: Vec<Arc<dyn Site>>) -> Self {
+        Self(sites)
+    }
+
+    // motorcyclespecs.co.za from `index_url` followed by the sources of the config.
+    pub fn load(index_url: &str, config: &SiteConfig) -> Result<Self> {
+        let mut sites: Vec<Arc<dyn Site>> = vec![Arc::new(ProfileSite::new("motorcyclespecs", index_url, Extractor::load(&config.profile)?))];
+        for source in &config.sources {
+            sites.push(Arc::new(ProfileSite::new(&source.name, &source.index_url, Extractor::load(&source.profile)?)));
+        }
+        Ok(Self::new(sites))
+    }
+
+    pub fn get_sites(&self) -> &[Arc<dyn Site>] {
+        &self.0
+    }
+
+    pub fn for_url(&self, url: &str) -> Option<&dyn Site> {
+        self.0.iter().find(|s| s.owns(url)).map(|s| s.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Sites;
+    use crate::config::{SiteConfig, SourceConfig};
+
+    #[test]
+    fn test_sites() {
+        let profile = std::env::temp_dir().join(format!("moto_spec_profile_{}.toml", std::process::id()));
+        std::fs::write(&profile, include_str!("../profiles/motorcyclespecs.toml")).unwrap();
+        let config = SiteConfig {
+            profile: String::new(),
+            sources: vec![SourceConfig {
+                name: "other".to_owned(),
+                index_url: "https://Other.example/bikes/".to_owned(),
+                profile: profile.to_str().unwrap().to_owned(),
+            }],
+        };
+        let sites = Sites::load("https://www.motorcyclespecs.co.za/index.htm", &config);
+        std::fs::remove_file(&profile).unwrap();
+        let sites = sites.unwrap();
+        assert_eq!(sites.get_sites().len(), 2);
+        assert_eq!(sites.for_url("https://www.motorcyclespecs.co.za/model/honda/cb500.html").map(|s| s.get_name()), Some("motorcyclespecs"));
+        assert_eq!(sites.for_url("https://other.example/m/1").map(|s| s.get_name()), Some("other"));
+        assert!(sites.for_url("https://unknown.example/").is_none());
+        assert!(sites.for_url("not a url").is_none());
+    }
+}
@@ -0,0 +1,147 @@
+use scraper::{ElementRef, Node};
+use unicode_normalization::UnicodeNormalization;
+
+// Elements rendered on lines of their own, their text is not run into the text around them.
+const BLOCKS: [&str; 27] = [
+    "address",
+    "article",
+    "blockquote",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+
+// Characters without a rendering, left behind by editors and copy-pasted text.
+const INVISIBLE: [char; 5] = ['\u{00ad}', '\u{200b}', '\u{200c}', '\u{200d}', '\u{feff}'];
+
+// The text of an element as it reads on the page, `normalize`d and with its lines joined by `sep`.
+pub fn clean(ele: ElementRef, sep: &str) -> String {
+    join(&lines(ele), sep)
+}
+
+// The text of an element split where the page breaks the line, at every `<br>` and block element.
+// Line breaks of the html source are plain whitespace and are kept within their line.
+pub fn lines(ele: ElementRef) -> Vec<String> {
+    let mut lines = vec![String::new()];
+    push_lines(ele, &mut lines);
+    lines
+}
+
+fn push_lines(ele: ElementRef, lines: &mut Vec<String>) {
+    let block = ele.value().name() == "br" || BLOCKS.contains(&ele.value().name());
+    if block {
+        new_line(lines);
+    }
+    for child in ele.children() {
+        match child.value() {
+            Node::Text(text) => lines.last_mut().unwrap().push_str(text),
+            Node::Element(_) => push_lines(ElementRef::wrap(child).unwrap(), lines),
+            _ => (),
+        }
+    }
+    if block {
+        new_line(lines);
+    }
+}
+
+fn new_line(lines: &mut Vec<String>) {
+    if !lines.last().unwrap().is_empty() {
+        lines.push(String::new());
+    }
+}
+
+// Normalizes every line and joins the non-empty ones with `sep`.
+pub fn join<S: AsRef<str>>(lines: &[S], sep: &str) -> String {
+    lines.iter().map(|l| normalize(l.as_ref())).filter(|l| !l.is_empty()).collect::<Vec<_>>().join(sep)
+}
+
+// A single line of text in canonical form: entities escaped twice by the page decoded, composed
+// unicode (NFC), invisible characters dropped and every run of whitespace, including non-breaking
+// and tab characters, turned into a single space with none at the ends.
+pub fn normalize(text: &str) -> String {
+    let text: String = decode_entities(text).nfc().filter(|c| !INVISIBLE.contains(c)).collect();
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Decodes the entities still found in text after parsing, as in "&amp;nbsp;" on the page. Unknown
+// entities are kept as they are.
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest[1..].find(';').filter(|end| *end <= 8).and_then(|end| entity(&rest[1..end + 1]).map(|c| (c, end + 2)));
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn entity(name: &str) -> Option<char> {
+    let code = match name {
+        "amp" => return Some('&'),
+        "lt" => return Some('<'),
+        "gt" => return Some('>'),
+        "quot" => return Some('"'),
+        "apos" => return Some('\''),
+        "nbsp" => return Some('\u{a0}'),
+        "deg" => return Some('°'),
+        _ => match name.strip_prefix('#')? {
+            hex if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16).ok()?,
+            dec => dec.parse().ok()?,
+        },
+    };
+    char::from_u32(code)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{clean, normalize};
+    use scraper::{Html, Selector};
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(" 35\u{a0}kW\t@ 9,500\n  rpm "), "35 kW @ 9,500 rpm");
+        assert_eq!(normalize("Cafe\u{301} Racer\u{200b}"), "Café Racer");
+        assert_eq!(normalize("2&nbsp;kg &#8211; &#x2013; &copy; R&D"), "2 kg – – &copy; R&D");
+    }
+
+    #[test]
+    fn test_clean() {
+        let html = Html::parse_fragment("<table><tr><td>Front: 120/70\n ZR17<br>Rear: 160/60 <b>ZR17</b><br><br><p>Tubeless</p></td></tr></table>");
+        let td = html.select(&Selector::parse("td").unwrap()).next().unwrap();
+        assert_eq!(clean(td, "; "), "Front: 120/70 ZR17; Rear: 160/60 ZR17; Tubeless");
+        assert_eq!(clean(td, " "), "Front: 120/70 ZR17 Rear: 160/60 ZR17 Tubeless");
+    }
+}
@@ -0,0 +1,78 @@
+use crate::config::DedupConfig;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use url::Url;
+
+// Urls already scheduled in this run, compared in normalized form.
+#[derive(Debug, Default)]
+pub struct Visited {
+    config: DedupConfig,
+    urls: Mutex<HashSet<String>>,
+}
+
+impl Visited {
+    pub fn new(config: &DedupConfig) -> Self {
+        Self {
+            config: config.clone(),
+            urls: Mutex::new(HashSet::new()),
+        }
+    }
+
+    // Returns true if the url has not been seen before.
+    pub fn insert(&self, url: &str) -> bool {
+        let url = self.normalize(url);
+        self.urls.lock().unwrap().insert(url)
+    }
+
+    // Drops the fragment and default port, sorts query parameters and optionally ignores the
+    // query or the case of the path. Unparsable urls are compared verbatim.
+    pub fn normalize(&self, url: &str) -> String {
+        let mut url = match Url::parse(url.trim()) {
+            Ok(url) => url,
+            Err(_) => return url.trim().to_owned(),
+        };
+        url.set_fragment(None);
+        if url.port() == url.port_or_known_default() {
+            let _ = url.set_port(None);
+        }
+        if self.config.ignore_query {
+            url.set_query(None);
+        } else if url.query().is_some() {
+            let mut pairs: Vec<(String, String)> = url.query_pairs().map(|(k, v)| (k.into_owned(), v.into_owned())).collect();
+            pairs.sort();
+            if pairs.is_empty() {
+                url.set_query(None);
+            } else {
+                url.query_pairs_mut().clear().extend_pairs(pairs);
+            }
+        }
+        if self.config.ignore_path_case {
+            let path = url.path().to_lowercase();
+            url.set_path(&path);
+        }
+        url.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Visited;
+    use crate::config::DedupConfig;
+
+    #[test]
+    fn test_visited() {
+        let visited = Visited::new(&DedupConfig::default());
+        assert!(visited.insert("https://www.motorcyclespecs.co.za/model/Honda/cb.html#top"));
+        assert!(!visited.insert("https://WWW.motorcyclespecs.co.za:443/model/Honda/cb.html"));
+        assert!(visited.insert("https://www.motorcyclespecs.co.za/model/honda/cb.html"));
+        assert!(visited.insert("https://a.com/list?b=2&a=1"));
+        assert!(!visited.insert("https://a.com/list?a=1&b=2"));
+
+        let visited = Visited::new(&DedupConfig {
+            ignore_path_case: true,
+            ignore_query: true,
+        });
+        assert!(visited.insert("https://a.com/Model/CB.html?ref=1"));
+        assert!(!visited.insert("https://a.com/model/cb.html"));
+    }
+}
@@ -0,0 +1,140 @@
+use serde::Serialize;
+
+// Earliest and latest model years accepted, other four digit numbers are engine sizes or prices.
+const FIRST_YEAR: i32 = 1850;
+const LAST_YEAR: i32 = 2100;
+
+// Words after the only year of a range still in production, as in "2020 on".
+const OPEN_ENDS: [&str; 8] = ["on", "onward", "onwards", "present", "to present", "to date", "now", "current"];
+
+// The model years of a listing row. `start` and `end` are inclusive, `end` is None for a range
+// still in production and both are None if no year could be read from `raw`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct YearRange {
+    raw: String,
+    start: Option<i32>,
+    end: Option<i32>,
+}
+
+impl YearRange {
+    // Reads "2013", "2015 - 18", "1987/88", "1998-2002", "2019-" and "2020 on"; two digit years
+    // continue the previous year, so "1998-02" ends in 2002.
+    pub fn parse(raw: &str) -> Self {
+        let mut years: Vec<i32> = vec![];
+        let mut tail = raw;
+        for (pos, digits) in numbers(raw) {
+            let year = match (digits.len(), years.last()) {
+                (4, _) => digits.parse().ok().filter(|y| (FIRST_YEAR..=LAST_YEAR).contains(y)),
+                (2, Some(&prev)) => digits.parse().ok().map(|yy: i32| expand(prev, yy)),
+                _ => None,
+            };
+            if let Some(year) = year {
+                years.push(year);
+                tail = &raw[pos + digits.len()..];
+            }
+        }
+        let (start, end) = match (years.iter().min(), years.iter().max()) {
+            (Some(&start), Some(_)) if years.len() == 1 && is_open_end(tail) => (Some(start), None),
+            (Some(&start), Some(&end)) => (Some(start), Some(end)),
+            _ => (None, None),
+        };
+        Self { raw: raw.to_owned(), start, end }
+    }
+
+    pub fn get_raw(&self) -> &str {
+        &self.raw
+    }
+
+    pub fn is_known(&self) -> bool {
+        self.start.is_some()
+    }
+
+    // Whether the range shares a year with `min..=max`, either bound may be open.
+    pub fn overlaps(&self, min: Option<i32>, max: Option<i32>) -> bool {
+        match self.start {
+            Some(start) => min.is_none_or(|min| self.end.is_none_or(|end| end >= min)) && max.is_none_or(|max| start <= max),
+            None => false,
+        }
+    }
+
+    // The same years written the same way, "2015 - 18" and "2015-2018" both give "2015-2018".
+    pub fn to_key(&self) -> String {
+        match (self.start, self.end) {
+            (Some(start), Some(end)) if start == end => start.to_string(),
+            (Some(start), Some(end)) => format!("{}-{}", start, end),
+            (Some(start), None) => format!("{}-", start),
+            _ => self.raw.trim().to_lowercase(),
+        }
+    }
+}
+
+// The runs of ascii digits in `s` with their byte offset.
+fn numbers(s: &str) -> Vec<(usize, &str)> {
+    let mut numbers = vec![];
+    let mut start = None;
+    for (i, c) in s.char_indices().chain(std::iter::once((s.len(), ' '))) {
+        match (c.is_ascii_digit(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(from)) => {
+                numbers.push((from, &s[from..i]));
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    numbers
+}
+
+// The first year ending in `yy` not before `prev`.
+fn expand(prev: i32, yy: i32) -> i32 {
+    let year = prev - prev % 100 + yy;
+    if year < prev {
+        year + 100
+    } else {
+        year
+    }
+}
+
+fn is_open_end(tail: &str) -> bool {
+    let tail = tail.trim().to_lowercase();
+    let rest = tail.trim_start_matches(['-', '–', '+']).trim();
+    (rest.len() < tail.len() && rest.is_empty()) || OPEN_ENDS.contains(&rest)
+}
+
+#[cfg(test)]
+mod test {
+    use super::YearRange;
+
+    fn bounds(raw: &str) -> (Option<i32>, Option<i32>) {
+        let range = YearRange::parse(raw);
+        (range.start, range.end)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(bounds("2013"), (Some(2013), Some(2013)));
+        assert_eq!(bounds("2015 - 18"), (Some(2015), Some(2018)));
+        assert_eq!(bounds("1987/88"), (Some(1987), Some(1988)));
+        assert_eq!(bounds("1998-02"), (Some(1998), Some(2002)));
+        assert_eq!(bounds("2008 - 2012"), (Some(2008), Some(2012)));
+        assert_eq!(bounds("2019-"), (Some(2019), None));
+        assert_eq!(bounds("2020 on"), (Some(2020), None));
+        assert_eq!(bounds("2021 - Present"), (Some(2021), None));
+        assert_eq!(bounds("2013 (EU)"), (Some(2013), Some(2013)));
+        assert_eq!(bounds("unknown"), (None, None));
+        assert_eq!(bounds("750"), (None, None));
+        assert_eq!(YearRange::parse(" 2015 - 18").get_raw(), " 2015 - 18");
+    }
+
+    #[test]
+    fn test_range() {
+        assert!(YearRange::parse("2015 - 18").overlaps(Some(2017), Some(2017)));
+        assert!(!YearRange::parse("2015 - 18").overlaps(Some(2019), None));
+        assert!(YearRange::parse("2019-").overlaps(Some(2030), None));
+        assert!(!YearRange::parse("2019-").overlaps(None, Some(2018)));
+        assert!(!YearRange::parse("unknown").overlaps(None, None));
+        assert_eq!(YearRange::parse("2015 - 18").to_key(), YearRange::parse("2015-2018").to_key());
+        assert_eq!(YearRange::parse("2020 on").to_key(), "2020-");
+        assert_eq!(YearRange::parse(" Unknown").to_key(), "unknown");
+    }
+}